@@ -58,7 +58,7 @@ impl Default for Interactable {
     }
 }
 
-#[derive(Debug, Default, Component)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Component)]
 #[storage(VecStorage)]
 pub struct Pos {
     pub x: f64,
@@ -71,10 +71,71 @@ pub struct Layer {
     pub z: f32,
 }
 
-#[derive(Debug, Default, Component)]
+#[derive(Debug, Default, Clone, Copy, Component)]
 #[storage(VecStorage)]
 pub struct Dimension {
     pub w: f64,
     pub h: f64,
 }
 
+
+/// A goal's propagated consensus weight, and that as a fraction of the heaviest goal's.
+#[derive(Debug, Default, Component)]
+#[storage(VecStorage)]
+pub struct Consensus {
+    pub score: f32,
+    pub share: f32,
+}
+
+/// Mirrors `ConsensusGoal::plugged`.
+#[derive(Debug, Default, Component)]
+#[storage(VecStorage)]
+pub struct Plugged(pub bool);
+
+/// How many unplugged leaves sit under a goal.
+#[derive(Debug, Default, Component)]
+#[storage(VecStorage)]
+pub struct OpenLeaves(pub usize);
+
+/// Kept in the world but neither drawn nor clickable.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct Hidden;
+
+/// Part of a cycle in what should be a DAG.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct InCycle;
+
+/// Where a goal or edge stands relative to the selected goal.
+#[derive(Debug, PartialEq, Component)]
+#[storage(VecStorage)]
+pub enum Lineage {
+    Focus,
+    Ancestor,
+    Descendant,
+    Dimmed,
+}
+
+/// A collapsed goal, and how many goals it is hiding.
+#[derive(Debug, Default, Component)]
+#[storage(VecStorage)]
+pub struct Collapsed(pub usize);
+
+/// A goal with sub-goals to collapse it over.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct Foldable;
+
+/// Where to draw an `Edge` whose ends are folded into collapsed goals.
+#[derive(Component)]
+#[storage(VecStorage)]
+pub struct Rerouted {
+    pub left: Entity,
+    pub right: Entity,
+}
+
+/// Held in place by the user; layouts leave it where it is.
+#[derive(Debug, Default, Component)]
+#[storage(NullStorage)]
+pub struct Pinned;
@@ -11,6 +11,7 @@ mod systems;
 
 fn init(mut _url: Url, orders: &mut impl Orders<Message>) -> Model {
     log!("I N I T I A L I Z E");
+    orders.send_msg(Message::CGGraphMessage(pages::cg_graph::Message::Restore));
 
     orders
         .perform_cmd(async {
@@ -30,6 +31,7 @@ impl Model {
     fn default() -> Self {
         Self {
             login: Some(pages::login::Model::default()),
+            graphs: pages::graph_list::Model::default(),
             subjects: pages::cg_graph::Model::default(),
         }
     }
@@ -39,6 +41,7 @@ impl Model {
 struct Model {
 
     login: Option<pages::login::Model>,
+    graphs: pages::graph_list::Model,
     subjects: pages::cg_graph::Model,
 }
 
@@ -46,6 +49,8 @@ struct Model {
 pub enum Message {
     GoodLogin(shared::User),
     LoginMsg(pages::login::Message),
+    GraphListMsg(pages::graph_list::Message),
+    OpenGraph(String),
     CGGraphMessage(pages::cg_graph::Message),
     NetworkError(fetch::FetchError),
 }
@@ -62,6 +67,7 @@ fn update(msg: Message, model: &mut Model, orders: &mut impl Orders<Message>) {
         GoodLogin(_usr) => {
             model.login = None;
             log!("good login");
+            orders.send_msg(GraphListMsg(pages::graph_list::Message::Fetch));
         }
         LoginMsg(msg) => {
             if let Some(GoodLogin(usr)) = pages::login::update(
@@ -72,6 +78,14 @@ fn update(msg: Message, model: &mut Model, orders: &mut impl Orders<Message>) {
                 orders.perform_cmd(async move { GoodLogin(usr) });
             }
         }
+        GraphListMsg(msg) => {
+            if let Some(msg) = pages::graph_list::update(msg, &mut model.graphs, &mut orders.proxy(GraphListMsg)) {
+                orders.send_msg(msg);
+            }
+        }
+        OpenGraph(id) => {
+            orders.send_msg(CGGraphMessage(pages::cg_graph::Message::Open(id)));
+        }
         CGGraphMessage(msg) => {
             pages::cg_graph::update(msg, &mut model.subjects, &mut orders.proxy(CGGraphMessage))
         }
@@ -90,6 +104,7 @@ fn view(mdl: &Model) -> Vec<Node<Message>> {
     };
     nodes![
         main_view,
+        nodes![pages::graph_list::view(&mdl.graphs)].map_msg(Message::GraphListMsg),
         pages::cg_graph::view(&mdl.subjects).map_msg(Message::CGGraphMessage),
     ]
 }
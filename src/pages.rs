@@ -1,2 +1,3 @@
 pub mod cg_graph;
+pub mod graph_list;
 pub mod login;
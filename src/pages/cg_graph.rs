@@ -1,25 +1,275 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
 use crate::systems::*;
 use crate::components::*;
-use petgraph::dot::{Config, Dot};
 use petgraph::prelude::*;
-use petgraph::visit::Bfs;
 use seed::{prelude::*, *};
 use specs::prelude::*;
-use std::collections::HashMap;
 use web_sys::HtmlCanvasElement;
 
+mod analysis;
+mod collapse;
+mod document;
+mod dot;
+mod ecs;
+mod edit;
+mod forest;
+mod history;
+mod io;
+mod layered;
+mod layout;
+mod patch;
+mod radial;
+mod route;
+mod search;
+mod snap;
+mod stats;
+mod store;
+mod svg;
+#[cfg(test)]
+mod test_util;
+mod world_index;
+pub use patch::{GraphPatch, PatchSet};
+pub use world_index::GraphWorldIndex;
+pub use edit::HANDLE;
+pub use route::{route_edges, EdgePath, EdgeRouting, Rect, RouteCache};
+pub use snap::{align, snap_to_grid, Guide, Guides, SnapGrid};
+pub use document::{GraphDocument, GraphError, ValidGraph, ValidationReport};
+
 pub const WIDTH: usize = 900;
 pub const HEIGHT: usize = 600;
 const RAD: u32 = 50;
+/// How far the view moves per press of a pan button or arrow key.
+const PAN: f64 = 100.;
+/// Longest frame animations advance by, so the first frame after a pause does not skip ahead.
+const FRAME: f64 = 0.1;
+/// How long positions have to stay put before they go to the backend.
+const SAVE_POSITIONS_MS: u32 = 1000;
 
 
 pub struct Model {
     pub pet: DiGraph<ConsensusGoal, ConsensusEdge>,
     canvas: ElRef<HtmlCanvasElement>,
     pub world: specs::World,
+    /// Which graph this is, for keeping it in browser storage.
+    pub graph_id: String,
+    pub report: ValidationReport,
+    /// The server revision `pet` reflects.
+    pub revision: u64,
+    /// Imported from a file, so only in this browser: nothing is sent to or fetched from the
+    /// backend for it.
+    local: bool,
+    rename: Option<edit::Rename>,
+    history: history::History,
+    /// Show only unplugged goals and the goals above them.
+    only_open: bool,
+    /// Refuse new edges that would close a cycle.
+    strict_dag: bool,
+    query: String,
+    /// Ids of the goals matching `query`, best first, and which of them is shown.
+    hits: Vec<usize>,
+    hit: Option<usize>,
+    /// Ids of the collapsed goals. Kept by id so reloads leave them collapsed.
+    collapsed: HashSet<usize>,
+    stats: stats::Stats,
+    layout: &'static dyn layout::LayoutStrategy,
+    /// Goals on their way to where `layout` put them.
+    transition: Option<layout::Transition>,
+    /// Let springs and repulsion move the goals around.
+    forces: bool,
+    /// Whether the force layout has yet to settle.
+    moving: bool,
+    /// Bumped on every move, so only the last of a burst of moves gets saved.
+    position_saves: u64,
+    /// Whether an `OnTick` is already coming.
+    ticking: bool,
 }
 impl Model {
+    fn canvas_pos(&self, ws_ev: &web_sys::MouseEvent) -> (f64, f64) {
+        let ox = self.canvas.get().unwrap().offset_left() as f64
+            - web_sys::window().unwrap().page_x_offset().unwrap();
+        let oy = self.canvas.get().unwrap().offset_top() as f64
+            - web_sys::window().unwrap().page_y_offset().unwrap();
+        let camera = self.world.read_resource::<Camera>();
+        (ws_ev.client_x() as f64 - ox + camera.x, ws_ev.client_y() as f64 - oy + camera.y)
+    }
+
+    /// Drops everything about the current graph for an empty `graph_id`, keeping the settings.
+    fn reopen(&mut self, graph_id: String) {
+        let old = std::mem::replace(self, Model{graph_id, ..Model::default()});
+        self.canvas = old.canvas;
+        self.only_open = old.only_open;
+        self.strict_dag = old.strict_dag;
+        self.layout = old.layout;
+        self.forces = old.forces;
+        *self.world.write_resource::<EdgeRouting>() = *old.world.read_resource::<EdgeRouting>();
+        *self.world.write_resource::<SnapGrid>() = *old.world.read_resource::<SnapGrid>();
+    }
+
+    /// Selects the goal and scrolls it into the middle of the canvas.
+    fn focus_goal(&mut self, id: usize) {
+        let ent = patch::goal_index(&self.pet, id)
+            .and_then(|idx| self.world.read_resource::<GraphWorldIndex>().node_entity(idx));
+        let ent = match ent {
+            Some(ent) => ent,
+            None => return,
+        };
+        if let (Some(pos), Some(dim)) = (
+            self.world.read_storage::<Pos>().get(ent),
+            self.world.read_storage::<Dimension>().get(ent),
+        ) {
+            let mut camera = self.world.write_resource::<Camera>();
+            camera.x = pos.x + dim.w / 2. - WIDTH as f64 / 2.;
+            camera.y = pos.y + dim.h / 2. - HEIGHT as f64 / 2.;
+        }
+        self.world.write_resource::<Selection>().0 = Some(ent);
+        self.highlight();
+    }
+
+    /// The goals the mouse is holding, by id, and where they are.
+    fn grabbed(&self) -> Vec<(usize, Pos)> {
+        let index = self.world.read_resource::<GraphWorldIndex>();
+        let (poss, states) = (self.world.read_storage::<Pos>(), self.world.read_storage::<Interactable>());
+        index
+            .nodes()
+            .filter_map(|(idx, ent)| match (poss.get(ent), states.get(ent)) {
+                (Some(pos), Some(Interactable::MouseDown(_, _))) => Some((self.pet[idx].id, *pos)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Pushes goals off whatever the mouse is about to drop, and counts them in the drag's undo.
+    fn separate_dropped(&mut self) {
+        let index = self.world.read_resource::<GraphWorldIndex>();
+        let (mut poss, dims, hidden, pins, states) = (
+            self.world.write_storage::<Pos>(),
+            self.world.read_storage::<Dimension>(),
+            self.world.read_storage::<Hidden>(),
+            self.world.read_storage::<Pinned>(),
+            self.world.read_storage::<Interactable>(),
+        );
+        let goals: Vec<(NodeIndex, Entity)> = index
+            .nodes()
+            .filter(|(_, ent)| poss.contains(*ent) && dims.contains(*ent) && !hidden.contains(*ent))
+            .collect();
+        let mut rects: Vec<Rect> = goals
+            .iter()
+            .map(|(_, ent)| Rect::of(poss.get(*ent).unwrap(), dims.get(*ent).unwrap()))
+            .collect();
+        let pinned: Vec<bool> = goals.iter().map(|(_, ent)| pins.contains(*ent)).collect();
+        // a click leaves everything where it was
+        let dropped: Vec<usize> = (0..goals.len())
+            .filter(|i| matches!(states.get(goals[*i].1), Some(Interactable::MouseDown(_, _))))
+            .filter(|i| {
+                let (idx, ent) = goals[*i];
+                self.history.drag_start(self.pet[idx].id) != poss.get(ent).copied()
+            })
+            .collect();
+        for i in dropped {
+            for j in snap::separate(&mut rects, i, &pinned) {
+                let (idx, ent) = goals[j];
+                let pos = poss.get_mut(ent).unwrap();
+                self.history.pushed(self.pet[idx].id, *pos);
+                *pos = Pos { x: rects[j].x, y: rects[j].y };
+            }
+        }
+    }
+
+    /// Re-scores the goals after any change to `pet`.
+    fn analyse(&mut self) {
+        self.stats = stats::stats(&self.pet);
+        // keep showing the same goal if it still matches
+        let shown = self.hit.and_then(|at| self.hits.get(at).copied());
+        self.hits = search::search(&self.pet, &self.query);
+        self.hit = shown.and_then(|id| self.hits.iter().position(|hit| *hit == id));
+        self.moving = true;
+        let scores = analysis::propagate(&self.pet, &analysis::WeightedSum);
+        world_index::apply_scores(&self.world, &scores, &analysis::shares(&scores));
+        world_index::apply_open_leaves(&self.world, &analysis::open_leaves(&self.pet));
+        world_index::apply_cycles(&self.world, &analysis::cycle_members(&self.pet));
+        let mut visible = if self.only_open {
+            analysis::open_with_ancestors(&self.pet)
+        } else {
+            vec![true; self.pet.node_count()]
+        };
+        let folding = collapse::fold(&self.pet, &self.collapsed);
+        for (shown, folded) in visible.iter_mut().zip(&folding.hidden) {
+            *shown &= !folded;
+        }
+        world_index::apply_visibility(&self.world, &visible);
+        world_index::apply_folding(&self.world, &self.pet, &folding);
+        self.highlight();
+    }
+
+    /// Re-ranks everything against the selected goal.
+    fn highlight(&self) {
+        let selected = self.world.read_resource::<Selection>().0;
+        let focus = selected.and_then(|ent| self.world.read_resource::<GraphWorldIndex>().entity_node(ent));
+        world_index::apply_lineage(&self.world, &self.pet, focus);
+    }
+
+    /// The graph and where its goals are, for saving.
+    fn snapshot(&self) -> io::GraphFile {
+        let positions = self
+            .pet
+            .node_indices()
+            .filter_map(|idx| {
+                let pos = world_index::node_pos(&self.world, idx)?;
+                Some(io::Placement{id: self.pet[idx].id, x: pos.x, y: pos.y})
+            })
+            .collect();
+        io::GraphFile{graph: GraphDocument::from_graph(&self.pet, self.revision), positions}
+    }
+
+    /// Where `layout` would put each goal, indexed like `pet`'s nodes.
+    fn lay_out(&self, layout: &dyn layout::LayoutStrategy) -> Vec<Pos> {
+        let sizes: Vec<Dimension> = self
+            .pet
+            .node_indices()
+            .map(|idx| {
+                let ent = self.world.read_resource::<GraphWorldIndex>().node_entity(idx);
+                let dims = self.world.read_storage::<Dimension>();
+                ent.and_then(|ent| dims.get(ent)).copied().unwrap_or_else(world_index::node_dimension)
+            })
+            .collect();
+        layout.place(&self.pet, &sizes, (WIDTH as f64, HEIGHT as f64))
+    }
+
+    /// Lays the `new` goals out the way `layout` would, each moved clear of the goals already on
+    /// the canvas. Goals are sized by their scores, so this goes after `analyse`.
+    fn place_new(&mut self, new: &HashSet<NodeIndex>) {
+        if new.is_empty() {
+            return;
+        }
+        let placed = self.lay_out(self.layout);
+        let rect = |world: &World, idx: NodeIndex, pos: Pos| {
+            let ent = world.read_resource::<GraphWorldIndex>().node_entity(idx);
+            let dim = ent.and_then(|ent| world.read_storage::<Dimension>().get(ent).copied());
+            Rect::of(&pos, &dim.unwrap_or_else(world_index::node_dimension))
+        };
+        let mut occupied: Vec<Rect> = self
+            .pet
+            .node_indices()
+            .filter(|idx| !new.contains(idx))
+            .filter_map(|idx| Some(rect(&self.world, idx, world_index::node_pos(&self.world, idx)?)))
+            .collect();
+        let mut new: Vec<NodeIndex> = new.iter().copied().collect();
+        new.sort();
+        for idx in new {
+            let pos = snap::clear_of(rect(&self.world, idx, placed[idx.index()]), &occupied);
+            world_index::set_node_pos(&mut self.world, idx, pos);
+            occupied.push(rect(&self.world, idx, pos));
+        }
+    }
+
+    /// Keeps the graph and its layout in the browser, so neither a reload nor a dead backend
+    /// loses them.
+    fn persist(&self) {
+        if let Err(err) = store::save(&self.graph_id, &self.snapshot()) {
+            log!(err.to_string());
+        }
+    }
+
     // fn detect_hover(&mut self, mouse_pos: (f32, f32)) {
     //     let positions = self.world.read_storage::<Pos>();
     //     for (pos, mut col) in (&positions, &mut cols).join() {
@@ -42,13 +292,51 @@ impl Default for Model {
         world.register::<Pos>();
         world.register::<Edge>();
         world.register::<Text>();
+        world.register::<Consensus>();
+        world.register::<Plugged>();
+        world.register::<OpenLeaves>();
+        world.register::<Hidden>();
+        world.register::<InCycle>();
+        world.register::<Lineage>();
+        world.register::<Foldable>();
+        world.register::<Collapsed>();
+        world.register::<Rerouted>();
+        world.register::<Pinned>();
+        ecs::register(&mut world);
         world.insert(MousePos::default());
+        world.insert(GraphWorldIndex::default());
+        world.insert(Selection::default());
+        world.insert(Connecting::default());
+        world.insert(Camera::default());
+        world.insert(EdgeRouting::default());
+        world.insert(RouteCache::<Entity>::default());
+        world.insert(SnapGrid::default());
+        world.insert(Guides::default());
         // world.register::<Color>();
         Self {
             pet: Default::default(),
             // fill_color: Color { r: 0, g: 255, b: 0 },
             canvas: Default::default(),
             world,
+            graph_id: "cg_graph".to_string(),
+            report: Default::default(),
+            revision: 0,
+            local: false,
+            rename: None,
+            history: Default::default(),
+            only_open: false,
+            strict_dag: false,
+            query: String::new(),
+            hits: vec![],
+            hit: None,
+            collapsed: HashSet::new(),
+            stats: Default::default(),
+            layout: &layout::Layered,
+            transition: None,
+            forces: false,
+            moving: false,
+            position_saves: 0,
+            ticking: false,
         }
     }
 }
@@ -69,22 +357,54 @@ impl Default for Model {
 #[derive(Debug)]
 pub enum Message {
     FetchCGGraph,
+    /// Loads the local save of the open graph, if there is one.
+    Restore,
     MakeDummyCGGraph,
-    CGGraph(fetch::Result<CGGraph>),
+    /// Switches to another graph, dropping everything about the current one but the settings.
+    Open(String),
+    CGGraph(String, fetch::Result<GraphDocument>),
+    SyncCGGraph,
+    CGPatches(String, fetch::Result<PatchSet>),
     OnTick(RenderInfo),
     CanvasMouse(web_sys::MouseEvent, Ev),
-    DotFile,
+    CanvasKey(web_sys::KeyboardEvent),
+    RenameInput(String),
+    RenameCommit,
+    RenameCancel,
+    Mutated(String, fetch::Result<u64>),
+    ToggleOnlyOpen,
+    ToggleStrictDag,
+    ToggleForces,
+    SetLayout(&'static dyn layout::LayoutStrategy),
+    SetRouting(EdgeRouting),
+    ToggleSnap,
+    SetGridSize(f64),
+    SavePositions(u64),
+    PositionsSaved(String, fetch::Result<()>),
+    /// Moves the view by this much.
+    Pan(f64, f64),
+    /// Puts the view back at the origin.
+    ResetView,
+    SearchInput(String),
+    SearchStep(isize),
+    SearchPick(usize),
+    ExportFile(io::Format),
+    ExportSvg,
+    ImportFile(web_sys::File),
+    Imported(String, Result<io::GraphFile, io::FileError>),
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Component)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Component)]
 #[storage(VecStorage)]
 pub struct ConsensusGoal {
     pub id: usize,
+    #[serde(default)]
+    pub root: bool,
     pub plugged: bool,
     pub st8mnt: String,
     pub weight: f32,
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ConsensusEdge {
     pub id: usize,
     pub label: String,
@@ -93,216 +413,393 @@ pub struct ConsensusEdge {
     pub weight: f32,
 }
 
-pub type CGGraph = (Vec<ConsensusGoal>, Vec<ConsensusEdge>);
 pub fn update(msg: Message, mdl: &mut Model, orders: &mut impl Orders<Message>) {
+    // only OnTick keeps the tick loop going, anything else just needs drawing again
+    let tick = matches!(msg, Message::OnTick(_));
+    handle(msg, mdl, orders);
+    if !tick && !mdl.ticking {
+        mdl.ticking = true;
+        orders.after_next_render(Message::OnTick);
+    }
+}
+
+fn handle(msg: Message, mdl: &mut Model, orders: &mut impl Orders<Message>) {
     use Message::*;
+    // answers for a graph we have since switched away from
+    match &msg {
+        CGGraph(id, _) | CGPatches(id, _) | Mutated(id, _) | PositionsSaved(id, _) if *id != mdl.graph_id => return,
+        _ => (),
+    }
     match msg {
         Message::OnTick(rend_inf) => {
+            let seconds = (rend_inf.timestamp_delta.unwrap_or(0.) / 1000.).min(FRAME);
+            if let Some(transition) = mdl.transition.as_mut() {
+                let (at, done) = transition.advance(seconds);
+                for (ent, pos) in at {
+                    if mdl.world.is_alive(ent) {
+                        mdl.world.write_storage::<Pos>().insert(ent, pos).expect("entity is alive");
+                    }
+                }
+                if done {
+                    mdl.transition = None;
+                    // the force layout starts over from here
+                    ecs::release(&mut mdl.world);
+                    mdl.moving = true;
+                    mdl.persist();
+                }
+            }
+            if mdl.forces && mdl.moving && mdl.transition.is_none() {
+                mdl.moving = ecs::step(&mut mdl.world, seconds);
+                if !mdl.moving {
+                    mdl.persist();
+                }
+            }
             let mut rendy = Renderer{canv_ref: mdl.canvas.clone()};
             rendy.run_now(&mdl.world);
-            orders.after_next_render(Message::OnTick);
+            // keep ticking only while something is on its way somewhere
+            mdl.ticking = mdl.transition.is_some() || (mdl.forces && mdl.moving);
+            if mdl.ticking {
+                orders.after_next_render(Message::OnTick);
+            }
         }
         MakeDummyCGGraph => {
-            let dummy: self::CGGraph = (vec![ConsensusGoal::default(), ConsensusGoal::default()], vec![]);
-            orders.perform_cmd(async{CGGraph(Ok(dummy))});
+            let dummy = GraphDocument::new(
+                vec![
+                    ConsensusGoal{id: 0, root: true, st8mnt: "root node".to_string(), ..Default::default()},
+                    ConsensusGoal{id: 1, ..Default::default()},
+                ],
+                vec![ConsensusEdge{id: 0, left: 0, right: 1, ..Default::default()}],
+            );
+            let id = mdl.graph_id.clone();
+            orders.perform_cmd(async move {CGGraph(id, Ok(dummy))});
         }
+        // an imported graph has no backend copy to fetch, sync or save to
+        FetchCGGraph | SyncCGGraph | SavePositions(_) if mdl.local => (),
         FetchCGGraph => {
             log!("F");
-            orders.perform_cmd(async { CGGraph(fetch_cg_graph().await) });
-        }
-        CGGraph(Ok(res)) => {
-            let mut gr = DiGraph::<ConsensusGoal, ConsensusEdge>::new();
-            let mut idx_map: HashMap<usize, NodeIndex> = HashMap::with_capacity(res.0.len());
-            let row_count: u32 = (WIDTH as u32) / (RAD * 2);
-            let mut root: Option<NodeIndex> = None;
-            for (i, node) in res.0.into_iter().enumerate() {
-                let r =  255 / (i as u8 + 1);
-                let g =  255 - (255 / (i as u8 + 1));
-                let b =  255;
-
-                let is_root = node.st8mnt.eq("root node");
-                let idx = gr.add_node(node);
-                if is_root {
-                    root = Some(idx);
+            let id = mdl.graph_id.clone();
+            orders.perform_cmd(async move { CGGraph(id.clone(), fetch_cg_graph(&id).await) });
+        }
+        Restore => {
+            // show the last save straight away; a fetch reconciles against it
+            match store::restore(&mdl.graph_id) {
+                Ok(Some(io::GraphFile{graph, positions})) => {
+                    mdl.revision = graph.revision;
+                    load(graph, &positions, mdl);
                 }
-
-                idx_map.insert(gr.raw_nodes()[idx.index()].weight.id, idx);
+                Ok(None) => (),
+                Err(err) => log!(err.to_string()),
             }
-            for edge in res.1.into_iter() {
-                gr.add_edge(
-                    *idx_map.get(&edge.left).unwrap(),
-                    *idx_map.get(&edge.right).unwrap(),
-                    edge,
-                );
+        }
+        Open(id) => {
+            mdl.reopen(id);
+            orders.send_msg(Restore);
+            orders.send_msg(FetchCGGraph);
+        }
+        CGGraph(_, Ok(mut doc)) => {
+            mdl.revision = doc.revision;
+            let positions = std::mem::take(&mut doc.positions);
+            load(doc, &positions, mdl);
+        }
+        SyncCGGraph => {
+            let since = mdl.revision;
+            let id = mdl.graph_id.clone();
+            orders.perform_cmd(async move { CGPatches(id.clone(), fetch_cg_patches(&id, since).await) });
+        }
+        CGPatches(_, Ok(set)) => {
+            // we missed something in between, only a full fetch can catch us up
+            if set.since != mdl.revision {
+                orders.send_msg(FetchCGGraph);
+                return;
             }
-            let mut i = 0;
-            let mut bfs = Bfs::new(&gr, root.unwrap());
-            let mut vec: VecDeque<NodeIndex<u32>> = Default::default();
-            let mut made: VecDeque<(Entity, NodeIndex<u32>)> = Default::default();
-            // 1. while there is a parent
-            while let Some(new_node) = bfs.next(&gr) {
-
-                let mut left: Entity;
-                vec.push_back(new_node);
-                log!(vec);
-                while let Some(idx) = vec.pop_front() {
-                    match made.iter().find(|(_, i)| *i == idx) {
-                        // already been made. Get the entity
-                        Some((ent, i)) => {
-                            left = *ent;
-                        }
-                        // Hasn't been made create it.
-                        None => {
-                            let x = ((i as u32 % (row_count as u32)) * (RAD * 2)) as f64;
-                            let y = ((i as u32 / (row_count as u32)) * (RAD * 2)) as f64;
-                            let dim = Dimension{
-                                w: RAD as f64,
-                                h: RAD as f64
-                            };
-                            let pos = Pos{x: x as f64, y: y as f64};
-                            let origin = Origin::Center;
-                            // let txt = Text{st: gr.node_weight(root.unwrap()).unwrap().id.to_string()};
-
-                            left = mdl.world
-                                              .create_entity()
-                                              .with(pos)
-                                              .with(dim)
-                                              .with(origin)
-                                              // .with(txt)
-                                              .with(Interactable::default())
-                                              .build();
-
-                            made.push_back((left, idx));
-                            i+=1;
-                        }
-
-                    }
-                    // so we have the left, now for the right
-                    gr.neighbors_directed(idx, Outgoing).for_each(|idx| {
-                        match made.iter().filter(|(_, i)| i.eq(&idx)).next() {
-                            // made node for the this neigbour
-                            Some((ent, _)) => {
-                                mdl.world.create_entity()
-                                    .with(Edge{left, right: *ent})
-                                    .build();
-                            }
-                            None => {
-                                let x = ((i as u32 % (row_count as u32)) * (RAD * 2)) as f64;
-                                let y = ((i as u32 / (row_count as u32)) * (RAD * 2)) as f64;
-                                let dim = Dimension{
-                                    w: RAD as f64,
-                                    h: RAD as f64
-                                };
-                                let pos = Pos{x: x as f64, y: y as f64};
-                                let origin = Origin::Center;
-                                // let txt = Text{st: gr.node_weight(root.unwrap()).unwrap().id.to_string()};
-                                let ent = mdl.world
-                                             .create_entity()
-                                             .with(pos)
-                                             .with(dim)
-                                             .with(origin)
-                                             // .with(txt)
-                                             .with(Interactable::default())
-                                             .build();
-                                made.push_back((ent, idx));
-                                vec.push_back(idx);
-                                i += 1;
-                                let edge = Edge{left, right: ent};
-                                mdl.world.create_entity()
-                                         .with(edge)
-                                         .build();
-                            }
-                        }
-                    });
+            let mut report = ValidationReport::default();
+            let mut added = vec![];
+            for patch in set.patches {
+                // the same gate a fetched graph goes through in `load`
+                if !admit(&patch, mdl) {
+                    continue;
+                }
+                let id = match &patch {
+                    GraphPatch::AddNode(goal) => Some(goal.id),
+                    _ => None,
+                };
+                match patch.apply(&mut mdl.world, &mut mdl.pet, |_| Pos::default()) {
+                    Ok(()) => added.extend(id),
+                    Err(err) => report.errors.push(err),
                 }
             }
-                // use a detached neighbors walker
-            //     let mut edges = gr.neighbors_directed(new_node, Outgoing).detach();
-            //     // 3. all the kids are connected here
-            //     while let Some((edge_i, node_i)) = edges.next(&gr) {
-            //         log!(edge_i, node_i);
-            //         i += 1;
-            //         let x = ((i as u32 % (row_count as u32)) * (RAD * 2)) as f64;
-            //         let y = ((i as u32 / (row_count as u32)) * (RAD * 2)) as f64;
-            //         let txt = Text{st: gr.node_weight(node_i).unwrap().id.to_string()};
-            //         let dim = Dimension{
-            //             w: RAD as f64,
-            //             h: RAD as f64
-            //         };
-            //         let pos = Pos{x: x as f64, y: y as f64};
-            //         let origin = Origin::Center;
-            //         let right = mdl.world
-            //            .create_entity()
-            //             .with(txt)
-            //            .with(pos)
-            //            .with(dim)
-            //            .with(origin)
-            //            .with(Interactable::default())
-            //            .build();
-            //         let edge = Edge {left, right};
-            //         mdl.world
-            //            .create_entity()
-            //            .with(edge)
-            //            .build();
-            //         left = right;
-            //         // 4. on last kid, need to make the kid the now parent
-            //     }
-            // }
-            mdl.pet = gr;
-            // for node in mdl.pet.raw_nodes() {
-            //     let node = &node.weight;
-            //     mdl.specs
-            //         .inner
-            //         .create_entity()
-            //         .with(Position {
-            //             x: node.pos_x as f32,
-            //             y: node.pos_y as f32,
-            //         })
-            //         .with(ecs::components::Color {
-            //             r: node.color.r,
-            //             g: node.color.g,
-            //             b: node.color.b,
-            //         })
-            //         .with(Renderable)
-            //         .build();
-            // }
-            // log!(mdl.pet.raw_nodes());
-            orders.after_next_render(Message::OnTick);
-        }
-        DotFile => log!(Dot::with_config(&mdl.pet, &[Config::EdgeNoLabel])),
+            mdl.analyse();
+            let added: HashSet<NodeIndex> = added.into_iter().filter_map(|id| patch::goal_index(&mdl.pet, id)).collect();
+            mdl.place_new(&added);
+            if report.is_clean() {
+                mdl.revision = set.revision;
+                mdl.persist();
+            } else {
+                log!(report);
+                mdl.report = report;
+                orders.send_msg(FetchCGGraph);
+            }
+        }
+        CGPatches(_, Err(err)) => {
+            // without the patches only a full fetch can catch us up
+            log!("patches not fetched", err);
+            orders.send_msg(FetchCGGraph);
+        }
+        ToggleOnlyOpen => {
+            mdl.only_open = !mdl.only_open;
+            mdl.analyse();
+        }
+        ToggleStrictDag => {
+            mdl.strict_dag = !mdl.strict_dag;
+        }
+        SetLayout(layout) => {
+            mdl.layout = layout;
+            let placed = mdl.lay_out(layout);
+            mdl.transition = Some(layout::Transition::to(&mdl.world, &mdl.pet, &placed));
+        }
+        SetRouting(routing) => {
+            *mdl.world.write_resource::<EdgeRouting>() = routing;
+        }
+        ToggleSnap => {
+            let mut grid = mdl.world.write_resource::<SnapGrid>();
+            grid.on = !grid.on;
+        }
+        SetGridSize(size) if size.is_finite() && size > 0. => mdl.world.write_resource::<SnapGrid>().size = size,
+        SetGridSize(_) => (),
+        SavePositions(n) if n == mdl.position_saves => {
+            let (id, positions) = (mdl.graph_id.clone(), mdl.snapshot().positions);
+            orders.perform_cmd(async move { PositionsSaved(id.clone(), post_cg_positions(&id, positions).await) });
+        }
+        // moved again since, a later save is on its way
+        SavePositions(_) => (),
+        PositionsSaved(_, Err(err)) => log!("positions not saved", err),
+        PositionsSaved(_, Ok(())) => (),
+        ToggleForces => {
+            mdl.forces = !mdl.forces;
+            mdl.moving = mdl.forces;
+            if !mdl.forces {
+                ecs::release(&mut mdl.world);
+                mdl.persist();
+            }
+        }
+        Pan(dx, dy) => {
+            let mut camera = mdl.world.write_resource::<Camera>();
+            camera.x += dx;
+            camera.y += dy;
+        }
+        ResetView => {
+            *mdl.world.write_resource::<Camera>() = Camera::default();
+        }
+        SearchInput(query) => {
+            mdl.hits = search::search(&mdl.pet, &query);
+            mdl.hit = None;
+            mdl.query = query;
+        }
+        SearchStep(by) => {
+            if !mdl.hits.is_empty() {
+                let len = mdl.hits.len() as isize;
+                let next = match mdl.hit {
+                    Some(at) => (at as isize + by).rem_euclid(len),
+                    None if by < 0 => len - 1,
+                    None => 0,
+                };
+                orders.send_msg(SearchPick(next as usize));
+            }
+        }
+        SearchPick(at) => {
+            if let Some(id) = mdl.hits.get(at).copied() {
+                mdl.hit = Some(at);
+                mdl.focus_goal(id);
+            }
+        }
+        ExportFile(format) => {
+            let saved = format
+                .encode(&mdl.snapshot())
+                .and_then(|bytes| io::download(&format!("{}.{}", mdl.graph_id, format.extension()), format.mime(), &bytes));
+            if let Err(err) = saved {
+                log!(err.to_string());
+            }
+        }
+        ExportSvg => {
+            let svg = svg::render(&mdl.world);
+            if let Err(err) = io::download(&format!("{}.svg", mdl.graph_id), "image/svg+xml", svg.as_bytes()) {
+                log!(err.to_string());
+            }
+        }
+        ImportFile(file) => {
+            let name = file.name();
+            orders.perform_cmd(async move { Imported(name, io::upload(file).await) });
+        }
+        Imported(name, Ok(file)) => {
+            // an imported graph is not the server's, even when it was exported from it, so it
+            // stays in this browser under an id of its own
+            let io::GraphFile{graph, positions} = file;
+            let stem = name.rsplitn(2, '.').last().unwrap_or_default();
+            mdl.graph_id = format!("{} (imported)", stem);
+            mdl.local = true;
+            mdl.revision = 0;
+            mdl.history = Default::default();
+            // goes through the same checks as a fetched graph
+            load(graph, &positions, mdl);
+        }
+        Imported(_, Err(err)) => log!(err.to_string()),
         CanvasMouse(ws_ev, ev) => {
+            let canv_pos = mdl.canvas_pos(&ws_ev);
             match ev {
                 Ev::MouseDown => {
-                    let ox = mdl.canvas.get().unwrap().offset_left() as f64
-                        - web_sys::window().unwrap().page_x_offset().unwrap();
-                    let oy = mdl.canvas.get().unwrap().offset_top() as f64
-                        - web_sys::window().unwrap().page_y_offset().unwrap();
-                    let canv_pos = (ws_ev.client_x() as f64 - ox, ws_ev.client_y() as f64 - oy);
-                    Interactable::MouseDown(canv_pos.0, canv_pos.1).run_now(&mdl.world);
-                    orders.after_next_render(Message::OnTick);
+                    // whatever the user grabs stays in their hand
+                    mdl.transition = None;
+                    let toggled = edit::toggle_at(&mdl.world, canv_pos.0, canv_pos.1)
+                        .and_then(|ent| mdl.world.read_resource::<GraphWorldIndex>().entity_node(ent));
+                    if let Some(idx) = toggled {
+                        let id = mdl.pet[idx].id;
+                        if !mdl.collapsed.remove(&id) {
+                            mdl.collapsed.insert(id);
+                        }
+                        mdl.analyse();
+                        return;
+                    }
+                    let connecting = edit::handle_at(&mdl.world, canv_pos.0, canv_pos.1);
+                    mdl.world.write_resource::<Selection>().0 = edit::node_at(&mdl.world, canv_pos.0, canv_pos.1)
+                        .or_else(|| edit::edge_at(&mdl.world, canv_pos.0, canv_pos.1));
+                    if connecting.is_none() {
+                        Interactable::MouseDown(canv_pos.0, canv_pos.1).run_now(&mdl.world);
+                        let grabbed = mdl.grabbed();
+                        mdl.history.begin_drag(grabbed);
+                    }
+                    mdl.world.write_resource::<Connecting>().0 = connecting;
+                    mdl.highlight();
                 }
                 Ev::MouseUp => {
+                    let from = mdl.world.write_resource::<Connecting>().0.take();
+                    // letting go over the goal it started from is a change of mind, not a loop
+                    let to = edit::node_at(&mdl.world, canv_pos.0, canv_pos.1).filter(|to| Some(*to) != from);
+                    if let (Some(from), Some(to)) = (from, to) {
+                        let index = mdl.world.read_resource::<GraphWorldIndex>();
+                        let ends = (index.entity_node(from), index.entity_node(to));
+                        drop(index);
+                        if let (Some(left), Some(right)) = ends {
+                            let edge = ConsensusEdge{
+                                id: edit::next_edge_id(&mdl.pet),
+                                left: mdl.pet[left].id,
+                                right: mdl.pet[right].id,
+                                ..Default::default()
+                            };
+                            perform(GraphPatch::AddEdge(edge), None, mdl, orders);
+                        }
+                    }
+                    mdl.separate_dropped();
+                    mdl.world.write_resource::<Guides>().0.clear();
+                    if mdl.history.end_drag(&mdl.world, &mdl.pet) {
+                        save_positions_later(mdl, orders);
+                    }
                     Interactable::MouseUp.run_now(&mdl.world);
-                    orders.after_next_render(Message::OnTick);
+                    mdl.moving = true;
+                    mdl.persist();
+                }
+                Ev::DblClick => {
+                    match edit::node_at(&mdl.world, canv_pos.0, canv_pos.1) {
+                        Some(ent) => {
+                            let idx = mdl.world.read_resource::<GraphWorldIndex>().entity_node(ent);
+                            mdl.rename = idx.map(|idx| edit::Rename{id: mdl.pet[idx].id, text: mdl.pet[idx].st8mnt.clone()});
+                        }
+                        None => {
+                            let goal = ConsensusGoal{id: edit::next_goal_id(&mdl.pet), ..Default::default()};
+                            let pos = Pos{x: canv_pos.0 - RAD as f64 / 2., y: canv_pos.1 - RAD as f64 / 2.};
+                            perform(GraphPatch::AddNode(goal), Some(pos), mdl, orders);
+                        }
+                    }
                 }
-                Ev::Click => {log!("click")}
-                Ev::DblClick => {log!("doubleclick")}
                 Ev::MouseMove => {
-                    let ox = mdl.canvas.get().unwrap().offset_left() as f64
-                        - web_sys::window().unwrap().page_x_offset().unwrap();
-                    let oy = mdl.canvas.get().unwrap().offset_top() as f64
-                        - web_sys::window().unwrap().page_y_offset().unwrap();
-                    let canv_pos = (ws_ev.client_x() as f64 - ox, ws_ev.client_y() as f64 - oy);
                     let mut up_pos = UpdateMousePos{x: canv_pos.0, y: canv_pos.1};
                     up_pos.run_now(&mdl.world);
                     Interactable::Hover.run_now(&mdl.world);
                     Drag.run_now(&mdl.world);
-                    orders.after_next_render(Message::OnTick);
+                    // the rest of the graph follows whatever is being dragged
+                    mdl.moving |= ws_ev.buttons() != 0;
                 }
+                Ev::Click => {}
                 _ => {log!("unhandled event")}
             }
             // mdl.detect_hover((x as f32, y as f32));
         }
+        CanvasKey(key_ev) => {
+            let key = key_ev.key();
+            match key.as_str() {
+                "z" | "Z" if key_ev.ctrl_key() || key_ev.meta_key() => {
+                    key_ev.prevent_default();
+                    let step = if key_ev.shift_key() { mdl.history.redo() } else { mdl.history.undo() };
+                    if let Some(step) = step {
+                        run_step(&step, mdl, orders);
+                    }
+                }
+                "Delete" => {
+                    let selected = mdl.world.write_resource::<Selection>().0.take();
+                    let index = mdl.world.read_resource::<GraphWorldIndex>();
+                    let patch = selected.and_then(|ent| match (index.entity_node(ent), index.entity_edge(ent)) {
+                        (Some(idx), _) => Some(GraphPatch::RemoveNode(mdl.pet[idx].id)),
+                        (_, Some(idx)) => Some(GraphPatch::RemoveEdge(mdl.pet[idx].id)),
+                        _ => None,
+                    });
+                    drop(index);
+                    if let Some(patch) = patch {
+                        perform(patch, None, mdl, orders);
+                    }
+                    mdl.highlight();
+                }
+                "Escape" => mdl.world.write_resource::<Connecting>().0 = None,
+                "ArrowLeft" | "ArrowRight" | "ArrowUp" | "ArrowDown" => {
+                    key_ev.prevent_default();
+                    let (dx, dy) = pan(&key);
+                    orders.send_msg(Message::Pan(dx, dy));
+                }
+                "p" | "P" => {
+                    if let Some(ent) = mdl.world.read_resource::<Selection>().0 {
+                        let mut pinned = mdl.world.write_storage::<Pinned>();
+                        if pinned.remove(ent).is_none() {
+                            pinned.insert(ent, Pinned).unwrap();
+                        }
+                    }
+                    mdl.moving = true;
+                }
+                _ => {}
+            }
+        }
+        RenameInput(text) => {
+            if let Some(rename) = mdl.rename.as_mut() {
+                rename.text = text;
+            }
+        }
+        RenameCommit => {
+            if let Some(edit::Rename{id, text}) = mdl.rename.take() {
+                if let Some(goal) = patch::goal_index(&mdl.pet, id).map(|idx| &mdl.pet[idx]) {
+                    let goal = ConsensusGoal{st8mnt: text, ..goal.clone()};
+                    perform(GraphPatch::UpdateNode(goal), None, mdl, orders);
+                }
+            }
+        }
+        RenameCancel => mdl.rename = None,
+        Mutated(_, Ok(revision)) => {
+            // someone else got a change in first, catch up on theirs too
+            if revision == mdl.revision + 1 {
+                mdl.revision = revision;
+                mdl.persist();
+            } else {
+                orders.send_msg(FetchCGGraph);
+            }
+        }
+        Mutated(_, Err(err)) => {
+            // the backend has not got our edit, so neither should we
+            log!("edit refused", err);
+            mdl.report.errors.push(GraphError::Refused(format!("{:?}", err)));
+            mdl.history = Default::default();
+            orders.send_msg(FetchCGGraph);
+        }
+        CGGraph(_, Err(err)) => {
+            // keep showing whatever we had, and say why it is not fresh
+            log!("graph not fetched", err);
+            mdl.report.errors.push(GraphError::Unreachable(format!("{:?}", err)));
+        }
         // Task(Ok((id, res))) => {
         //     mdl.subjects.get_mut(&id).unwrap().learning_objectives = res;
         // }
@@ -310,8 +807,168 @@ pub fn update(msg: Message, mdl: &mut Model, orders: &mut impl Orders<Message>)
     }
 }
 
-async fn fetch_cg_graph() -> fetch::Result<CGGraph> {
-    let result = Request::new("api/graph/cg_graph")
+/// Swaps `pet` for the validated `doc`. Goals listed in `positions` go there, goals already on the
+/// canvas stay put, and the rest are laid out tree by tree.
+fn load(doc: GraphDocument, positions: &[io::Placement], mdl: &mut Model) {
+    let ValidGraph{graph: mut gr, mut report, ..} = doc.validate();
+    if mdl.strict_dag {
+        let closing = analysis::back_edges(&gr);
+        for edge in &closing {
+            let (left, right) = gr.edge_endpoints(*edge).expect("edge index from this graph");
+            report.errors.push(GraphError::ClosesCycle{edge: gr[*edge].id, left: gr[left].id, right: gr[right].id});
+        }
+        gr.retain_edges(|_, edge| !closing.contains(&edge));
+    }
+    if !report.is_clean() {
+        log!(report);
+    }
+    mdl.report = report;
+    let mut fresh = vec![];
+    world_index::reconcile(&mut mdl.world, &mdl.pet, &gr, |idx| {
+        fresh.push(idx);
+        Pos::default()
+    });
+    mdl.pet = gr;
+    mdl.transition = None;
+    mdl.analyse();
+    let ids: HashMap<usize, NodeIndex> = mdl.pet.node_indices().map(|idx| (mdl.pet[idx].id, idx)).collect();
+    let mut missing: HashSet<NodeIndex> = fresh.into_iter().collect();
+    for place in positions {
+        if let Some(idx) = ids.get(&place.id) {
+            world_index::set_node_pos(&mut mdl.world, *idx, Pos{x: place.x, y: place.y});
+            missing.remove(idx);
+        }
+    }
+    mdl.place_new(&missing);
+    mdl.persist();
+}
+
+/// Applies a local edit, then hands it to the backend. A new goal goes to `pos`, or where the
+/// layout has room for it.
+fn commit(patch: GraphPatch, mdl: &mut Model, orders: &mut impl Orders<Message>, pos: Option<Pos>) -> bool {
+    let sent = patch.clone();
+    let added = match &patch {
+        GraphPatch::AddNode(goal) if pos.is_none() => Some(goal.id),
+        _ => None,
+    };
+    if let Err(err) = patch.apply(&mut mdl.world, &mut mdl.pet, |_| pos.unwrap_or_default()) {
+        log!(err);
+        mdl.report.errors.push(err);
+        return false;
+    }
+    mdl.analyse();
+    let added: HashSet<NodeIndex> = added.and_then(|id| patch::goal_index(&mdl.pet, id)).into_iter().collect();
+    mdl.place_new(&added);
+    mdl.persist();
+    if !mdl.local {
+        let id = mdl.graph_id.clone();
+        orders.perform_cmd(async move { Message::Mutated(id.clone(), post_cg_patch(&id, sent).await) });
+    }
+    true
+}
+
+/// Turns away edges that would close a cycle while `strict_dag` is on, saying why in the report.
+fn admit(patch: &GraphPatch, mdl: &mut Model) -> bool {
+    let edge = match patch {
+        GraphPatch::AddEdge(edge) | GraphPatch::UpdateEdge(edge) if mdl.strict_dag => edge,
+        _ => return true,
+    };
+    let ends = (patch::goal_index(&mdl.pet, edge.left), patch::goal_index(&mdl.pet, edge.right));
+    if let (Some(left), Some(right)) = ends {
+        if analysis::closes_cycle(&mdl.pet, left, right) {
+            let err = GraphError::ClosesCycle{edge: edge.id, left: edge.left, right: edge.right};
+            log!(err);
+            mdl.report.errors.push(err);
+            return false;
+        }
+    }
+    true
+}
+
+/// A user edit: committed like any other, and undoable. New goals go to `pos`.
+fn perform(patch: GraphPatch, pos: Option<Pos>, mdl: &mut Model, orders: &mut impl Orders<Message>) {
+    if !admit(&patch, mdl) {
+        return;
+    }
+    let undo = history::inverse(&mdl.world, &mdl.pet, &patch);
+    let mut redo = history::Step{patches: vec![patch.clone()], moves: vec![]};
+    if let (GraphPatch::AddNode(goal), Some(pos)) = (&patch, pos) {
+        redo.moves.push((goal.id, pos));
+    }
+    if commit(patch, mdl, orders, pos) {
+        mdl.history.push(history::Command{undo, redo});
+    }
+}
+
+fn run_step(step: &history::Step, mdl: &mut Model, orders: &mut impl Orders<Message>) {
+    for patch in &step.patches {
+        if !admit(patch, mdl) {
+            continue;
+        }
+        let pos = match patch {
+            GraphPatch::AddNode(goal) => step.place(goal.id),
+            _ => None,
+        };
+        commit(patch.clone(), mdl, orders, pos);
+    }
+    for (id, pos) in &step.moves {
+        if let Some(idx) = patch::goal_index(&mdl.pet, *id) {
+            world_index::set_node_pos(&mut mdl.world, idx, *pos);
+        }
+    }
+    mdl.persist();
+    if !step.moves.is_empty() {
+        save_positions_later(mdl, orders);
+    }
+}
+
+/// Sends the positions once nothing has moved for `SAVE_POSITIONS_MS`.
+fn save_positions_later(mdl: &mut Model, orders: &mut impl Orders<Message>) {
+    mdl.position_saves += 1;
+    let n = mdl.position_saves;
+    orders.perform_cmd(cmds::timeout(SAVE_POSITIONS_MS, move || Message::SavePositions(n)));
+}
+
+/// The backend URL of `graph`, followed by `rest`.
+fn graph_url(graph: &str, rest: &str) -> String {
+    format!("api/graph/{}{}", String::from(js_sys::encode_uri_component(graph)), rest)
+}
+
+async fn post_cg_positions(graph: &str, positions: Vec<io::Placement>) -> fetch::Result<()> {
+    Request::new(graph_url(graph, "/positions"))
+        .method(Method::Post)
+        .json(&positions)?
+        .fetch()
+        .await?
+        .check_status()?;
+    Ok(())
+}
+
+async fn post_cg_patch(graph: &str, patch: GraphPatch) -> fetch::Result<u64> {
+    let result = Request::new(graph_url(graph, "/patch"))
+        .method(Method::Post)
+        .json(&patch)?
+        .fetch()
+        .await?
+        .check_status()?
+        .json()
+        .await?;
+    Ok(result)
+}
+
+async fn fetch_cg_graph(graph: &str) -> fetch::Result<GraphDocument> {
+    let result = Request::new(graph_url(graph, ""))
+        .method(Method::Get)
+        .fetch()
+        .await?
+        .check_status()?
+        .json()
+        .await?;
+    Ok(result)
+}
+
+async fn fetch_cg_patches(graph: &str, since: u64) -> fetch::Result<PatchSet> {
+    let result = Request::new(graph_url(graph, &format!("/patches?since={}", since)))
         .method(Method::Get)
         .fetch()
         .await?
@@ -322,40 +979,237 @@ async fn fetch_cg_graph() -> fetch::Result<CGGraph> {
 }
 
 pub fn view(model: &Model) -> Node<Message> {
+    let routing_now = *model.world.read_resource::<EdgeRouting>();
+    let grid = *model.world.read_resource::<SnapGrid>();
     ul![
         li![button![
             "get cg_graph with backend",
             ev(Ev::Click, |_| Message::FetchCGGraph)
         ]],
+        li![button![
+            "sync cg_graph with backend",
+            ev(Ev::Click, |_| Message::SyncCGGraph)
+        ]],
         li![button![
             "get cg_graph without backend",
             ev(Ev::Click, |_| Message::MakeDummyCGGraph)
         ]],
-        canvas![
-            el_ref(&model.canvas),
-            attrs![
-                At::Width => px(WIDTH),
-                At::Height => px(HEIGHT),
+        li![button![
+            if model.only_open { "show all goals" } else { "show only open goals" },
+            ev(Ev::Click, |_| Message::ToggleOnlyOpen)
+        ]],
+        li![label![
+            "layout ",
+            select![
+                layout::ALL.iter().map(|layout| option![
+                    attrs![At::Value => layout.name(), At::Selected => (layout.name() == model.layout.name()).as_at_value()],
+                    layout.name(),
+                ]),
+                input_ev(Ev::Change, |name| layout::by_name(&name).map(Message::SetLayout)),
+            ],
+        ]],
+        li![label![
+            "edges ",
+            select![
+                EdgeRouting::ALL.iter().map(|routing| option![
+                    attrs![At::Value => routing.name(), At::Selected => (*routing == routing_now).as_at_value()],
+                    routing.name(),
+                ]),
+                input_ev(Ev::Change, |name| EdgeRouting::from_name(&name).map(Message::SetRouting)),
+            ],
+        ]],
+        li![label![
+            input![
+                attrs![At::Type => "checkbox", At::Checked => model.forces.as_at_value()],
+                ev(Ev::Change, |_| Message::ToggleForces),
+            ],
+            "force layout (p pins the selected goal)",
+        ]],
+        li![
+            label![
+                input![
+                    attrs![At::Type => "checkbox", At::Checked => grid.on.as_at_value()],
+                    ev(Ev::Change, |_| Message::ToggleSnap),
+                ],
+                "snap to a grid of ",
+            ],
+            input![
+                attrs![At::Type => "number", At::Min => 1, At::Value => grid.size],
+                input_ev(Ev::Change, |size| size.parse().ok().map(Message::SetGridSize)),
+            ],
+            "px",
+        ],
+        li![label![
+            input![
+                attrs![At::Type => "checkbox", At::Checked => model.strict_dag.as_at_value()],
+                ev(Ev::Change, |_| Message::ToggleStrictDag),
+            ],
+            "refuse edges that close a cycle",
+        ]],
+        li![search_view(model)],
+        li![
+            "view ",
+            ["ArrowLeft", "ArrowUp", "ArrowDown", "ArrowRight"].iter().zip(&["←", "↑", "↓", "→"]).map(|(key, arrow)| {
+                let (dx, dy) = pan(key);
+                button![*arrow, ev(Ev::Click, move |_| Message::Pan(dx, dy))]
+            }),
+            button!["reset", ev(Ev::Click, |_| Message::ResetView)],
+        ],
+        div![
+            style![St::Display => "flex"],
+            div![
+                style![St::Position => "relative", St::Width => px(WIDTH)],
+                canvas_view(model),
+                model.rename.as_ref().map(|rename| rename_view(model, rename)),
             ],
-            style![
-                St::Border => "1px solid black",
+            stats_view(&model.stats),
+        ],
+        file_view(),
+        IF!(!model.report.is_clean() => div![
+            "graph loaded with problems:",
+            ul![model.report.errors.iter().map(|err| li![err.to_string()])],
+        ]),
+    ]
+}
+
+/// Which way an arrow key moves the view.
+fn pan(key: &str) -> (f64, f64) {
+    match key {
+        "ArrowLeft" => (-PAN, 0.),
+        "ArrowRight" => (PAN, 0.),
+        "ArrowUp" => (0., -PAN),
+        _ => (0., PAN),
+    }
+}
+
+fn stats_view(stats: &stats::Stats) -> Node<Message> {
+    let row = |name: &str, value: String| tr![td![name], td![value]];
+    let weights = &stats.weights;
+    table![
+        style![St::MarginLeft => px(10)],
+        row("goals", stats.goals.to_string()),
+        row("edges", stats.edges.to_string()),
+        row("roots", stats.roots.to_string()),
+        row("leaves", stats.leaves.to_string()),
+        row("max depth", stats.max_depth.to_string()),
+        row("average fan-out", format!("{:.2}", stats.fan_out)),
+        row("plugged / unplugged", format!("{} / {}", stats.plugged, stats.unplugged)),
+        row("weight min / max", format!("{} / {}", weights.min, weights.max)),
+        row("weight mean / median", format!("{:.2} / {}", weights.mean, weights.median)),
+        row(
+            "components",
+            stats.components.iter().map(usize::to_string).collect::<Vec<_>>().join(", "),
+        ),
+    ]
+}
+
+fn file_view() -> Node<Message> {
+    div![
+        io::Format::ALL.iter().map(|format| {
+            let format = *format;
+            button![
+                format!("export .{}", format.extension()),
+                ev(Ev::Click, move |_| Message::ExportFile(format)),
+            ]
+        }),
+        button!["export .svg", ev(Ev::Click, |_| Message::ExportSvg)],
+        label![
+            " import ",
+            input![
+                attrs![At::Type => "file", At::Accept => ".json,.ron,.bson,.dot,.gv"],
+                ev(Ev::Change, |event| {
+                    let input: web_sys::HtmlInputElement = event.target()?.unchecked_into();
+                    input.files()?.get(0).map(Message::ImportFile)
+                }),
+            ],
+        ],
+    ]
+}
+
+fn search_view(model: &Model) -> Node<Message> {
+    div![
+        input![
+            attrs![
+                At::Type => "search",
+                At::Value => model.query,
+                At::Placeholder => "search goals",
             ],
-            mouse_ev(Ev::MouseDown, |mouse_event| {
-                Message::CanvasMouse(mouse_event.unchecked_into(), Ev::MouseDown)
+            input_ev(Ev::Input, Message::SearchInput),
+            keyboard_ev(Ev::KeyDown, |keyboard_event| match keyboard_event.key().as_str() {
+                "Enter" if keyboard_event.shift_key() => Some(Message::SearchStep(-1)),
+                "Enter" | "ArrowDown" => Some(Message::SearchStep(1)),
+                "ArrowUp" => Some(Message::SearchStep(-1)),
+                _ => None,
             }),
-            mouse_ev(Ev::MouseUp, |mouse_event| Message::CanvasMouse(
-                mouse_event.unchecked_into(), Ev::MouseUp
-            )),
-            mouse_ev(Ev::Click, |mouse_event| Message::CanvasMouse(
-                mouse_event.unchecked_into(), Ev::Click
-            )),
-            mouse_ev(Ev::DblClick, |mouse_event| Message::CanvasMouse(
-                mouse_event.unchecked_into(), Ev::DblClick
-            )),
-            mouse_ev(Ev::MouseMove, |mouse_event| Message::CanvasMouse(
-                mouse_event.unchecked_into(), Ev::MouseMove
-            ))
         ],
-        button!["get .dot file", ev(Ev::Click, |_| Message::DotFile)],
+        button!["previous", ev(Ev::Click, |_| Message::SearchStep(-1))],
+        button!["next", ev(Ev::Click, |_| Message::SearchStep(1))],
+        IF!(!model.query.is_empty() => span![format!(" {} found", model.hits.len())]),
+        ol![model.hits.iter().take(20).enumerate().map(|(at, id)| {
+            let st8mnt = patch::goal_index(&model.pet, *id).map_or("", |idx| &model.pet[idx].st8mnt);
+            li![
+                style![St::FontWeight => if model.hit == Some(at) { "bold" } else { "normal" }],
+                st8mnt,
+                ev(Ev::Click, move |_| Message::SearchPick(at)),
+            ]
+        })],
+    ]
+}
+
+fn canvas_view(model: &Model) -> Node<Message> {
+    canvas![
+        el_ref(&model.canvas),
+        attrs![
+            At::Width => px(WIDTH),
+            At::Height => px(HEIGHT),
+            At::TabIndex => 0,
+        ],
+        style![
+            St::Border => "1px solid black",
+        ],
+        mouse_ev(Ev::MouseDown, |mouse_event| {
+            Message::CanvasMouse(mouse_event.unchecked_into(), Ev::MouseDown)
+        }),
+        mouse_ev(Ev::MouseUp, |mouse_event| Message::CanvasMouse(
+            mouse_event.unchecked_into(), Ev::MouseUp
+        )),
+        mouse_ev(Ev::Click, |mouse_event| Message::CanvasMouse(
+            mouse_event.unchecked_into(), Ev::Click
+        )),
+        mouse_ev(Ev::DblClick, |mouse_event| Message::CanvasMouse(
+            mouse_event.unchecked_into(), Ev::DblClick
+        )),
+        mouse_ev(Ev::MouseMove, |mouse_event| Message::CanvasMouse(
+            mouse_event.unchecked_into(), Ev::MouseMove
+        )),
+        keyboard_ev(Ev::KeyDown, Message::CanvasKey),
+    ]
+}
+
+fn rename_view(model: &Model, rename: &edit::Rename) -> Node<Message> {
+    let index = model.world.read_resource::<GraphWorldIndex>();
+    let poss = model.world.read_storage::<Pos>();
+    let camera = model.world.read_resource::<Camera>();
+    let (x, y) = patch::goal_index(&model.pet, rename.id)
+        .and_then(|idx| index.node_entity(idx))
+        .and_then(|ent| poss.get(ent))
+        .map_or((0., 0.), |pos| (pos.x - camera.x, pos.y - camera.y));
+    input![
+        attrs![
+            At::Value => rename.text,
+            At::AutoFocus => true,
+        ],
+        style![
+            St::Position => "absolute",
+            St::Left => px(x),
+            St::Top => px(y),
+        ],
+        input_ev(Ev::Input, Message::RenameInput),
+        keyboard_ev(Ev::KeyDown, |keyboard_event| match keyboard_event.key().as_str() {
+            "Enter" => Some(Message::RenameCommit),
+            "Escape" => Some(Message::RenameCancel),
+            _ => None,
+        }),
+        ev(Ev::Blur, |_| Message::RenameCommit),
     ]
 }
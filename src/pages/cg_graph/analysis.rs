@@ -0,0 +1,265 @@
+use super::{ConsensusEdge, ConsensusGoal};
+use petgraph::algo::{has_path_connecting, tarjan_scc};
+use petgraph::prelude::*;
+use petgraph::visit::{
+    depth_first_search, Dfs, DfsEvent, DfsPostOrder, IntoNeighbors, Reversed, VisitMap, Visitable, Walker,
+};
+
+/// Combines a goal's own weight with `(edge weight, sub-goal score)` for each of its sub-goals.
+pub trait Aggregate {
+    fn combine(&self, own: f32, children: &[(f32, f32)]) -> f32;
+}
+
+impl<F: Fn(f32, &[(f32, f32)]) -> f32> Aggregate for F {
+    fn combine(&self, own: f32, children: &[(f32, f32)]) -> f32 {
+        self(own, children)
+    }
+}
+
+/// `own + Σ edge * child`
+pub struct WeightedSum;
+
+impl Aggregate for WeightedSum {
+    fn combine(&self, own: f32, children: &[(f32, f32)]) -> f32 {
+        own + children.iter().map(|(edge, child)| edge * child).sum::<f32>()
+    }
+}
+
+/// Scores every goal from its sub-goals up, indexed like the graph's nodes. An edge that closes a
+/// cycle contributes nothing to the goal it comes from.
+pub fn propagate(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, agg: &impl Aggregate) -> Vec<f32> {
+    let mut scores: Vec<Option<f32>> = vec![None; graph.node_count()];
+    let mut dfs = DfsPostOrder::empty(graph);
+    let mut children = vec![];
+    for start in graph.node_indices() {
+        if dfs.discovered.is_visited(&start) {
+            continue;
+        }
+        dfs.move_to(start);
+        while let Some(idx) = dfs.next(graph) {
+            children.clear();
+            children.extend(
+                graph
+                    .edges_directed(idx, Outgoing)
+                    .filter_map(|e| Some((e.weight().weight, scores[e.target().index()]?))),
+            );
+            scores[idx.index()] = Some(agg.combine(graph[idx].weight, &children));
+        }
+    }
+    scores.into_iter().map(|s| s.unwrap_or_default()).collect()
+}
+
+/// Each score as a fraction of the largest magnitude, for sizing and colouring.
+pub fn shares(scores: &[f32]) -> Vec<f32> {
+    let max = scores.iter().fold(0f32, |max, s| max.max(s.abs()));
+    if max == 0. {
+        return vec![0.5; scores.len()];
+    }
+    scores.iter().map(|s| s.abs() / max).collect()
+}
+
+/// For every goal, how many distinct unplugged leaves sit under it, itself included.
+pub fn open_leaves(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<usize> {
+    let is_open_leaf =
+        |idx: &NodeIndex| !graph[*idx].plugged && graph.neighbors_directed(*idx, Outgoing).next().is_none();
+    // components come sub-goals first, so each one only merges sets that are already done
+    let components = tarjan_scc(graph);
+    let mut component = vec![0; graph.node_count()];
+    for (c, members) in components.iter().enumerate() {
+        for idx in members {
+            component[idx.index()] = c;
+        }
+    }
+    let mut leaves: Vec<Vec<NodeIndex>> = Vec::with_capacity(components.len());
+    for (c, members) in components.iter().enumerate() {
+        let mut under: Vec<NodeIndex> = members.iter().copied().filter(is_open_leaf).collect();
+        for idx in members {
+            for child in graph.neighbors(*idx).map(|child| component[child.index()]).filter(|cc| *cc != c) {
+                under.extend_from_slice(&leaves[child]);
+            }
+        }
+        under.sort();
+        under.dedup();
+        leaves.push(under);
+    }
+    component.iter().map(|c| leaves[*c].len()).collect()
+}
+
+/// Marks the unplugged goals and every goal they support, directly or not.
+pub fn open_with_ancestors(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<bool> {
+    let mut keep = vec![false; graph.node_count()];
+    let mut dfs = Dfs::empty(Reversed(graph));
+    for start in graph.node_indices().filter(|idx| !graph[*idx].plugged) {
+        if dfs.discovered.is_visited(&start) {
+            continue;
+        }
+        dfs.move_to(start);
+        while let Some(idx) = dfs.next(Reversed(graph)) {
+            keep[idx.index()] = true;
+        }
+    }
+    keep
+}
+
+fn reachable<G>(graph: G, count: usize, from: NodeIndex) -> Vec<bool>
+where
+    G: IntoNeighbors<NodeId = NodeIndex> + Visitable + Copy,
+{
+    let mut seen = vec![false; count];
+    for idx in Dfs::new(graph, from).iter(graph).skip(1) {
+        seen[idx.index()] = true;
+    }
+    seen
+}
+
+/// Every goal `idx` supports, directly or not.
+pub fn ancestors(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, idx: NodeIndex) -> Vec<bool> {
+    reachable(Reversed(graph), graph.node_count(), idx)
+}
+
+/// Every sub-goal of `idx`, directly or not.
+pub fn descendants(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, idx: NodeIndex) -> Vec<bool> {
+    reachable(graph, graph.node_count(), idx)
+}
+
+/// The goals of every cycle, one `Vec` per strongly connected component that has any.
+pub fn cycles(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<Vec<NodeIndex>> {
+    tarjan_scc(graph).into_iter().filter(|scc| scc.len() > 1).collect()
+}
+
+pub fn cycle_members(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<bool> {
+    let mut members = vec![false; graph.node_count()];
+    for idx in cycles(graph).into_iter().flatten() {
+        members[idx.index()] = true;
+    }
+    members
+}
+
+/// Whether an edge from `left` to `right` would leave the graph with a cycle in it.
+pub fn closes_cycle(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, left: NodeIndex, right: NodeIndex) -> bool {
+    left == right || has_path_connecting(graph, right, left, None)
+}
+
+/// Edges that close a cycle, as a depth first search from the parentless goals meets them.
+/// Without them the graph has no cycles.
+pub fn back_edges(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<EdgeIndex> {
+    let parentless = graph.node_indices().filter(|idx| graph.neighbors_directed(*idx, Incoming).next().is_none());
+    let mut closing = vec![];
+    depth_first_search(graph, parentless.chain(graph.node_indices()), |event| {
+        if let DfsEvent::BackEdge(left, right) = event {
+            closing.extend(graph.edges(left).filter(|e| e.target() == right).map(|e| e.id()));
+        }
+    });
+    closing.sort();
+    closing.dedup();
+    closing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::weighted;
+    use super::*;
+
+    #[test]
+    fn chain_sums_through_edge_weights() {
+        let graph = weighted(&[1., 2., 4.], &[(0, 1, 0.5), (1, 2, 0.5)]);
+        assert_eq!(propagate(&graph, &WeightedSum), vec![3., 4., 4.]);
+    }
+
+    #[test]
+    fn diamond_counts_shared_subgoal_on_both_paths() {
+        let graph = weighted(&[0., 1., 1., 2.], &[(0, 1, 1.), (0, 2, 1.), (1, 3, 1.), (2, 3, 1.)]);
+        assert_eq!(propagate(&graph, &WeightedSum), vec![6., 3., 3., 2.]);
+    }
+
+    #[test]
+    fn forests_score_independently() {
+        let graph = weighted(&[1., 1., 5., 1.], &[(0, 1, 1.), (2, 3, 2.)]);
+        assert_eq!(propagate(&graph, &WeightedSum), vec![2., 1., 7., 1.]);
+    }
+
+    #[test]
+    fn custom_aggregate() {
+        let max = |own: f32, children: &[(f32, f32)]| children.iter().fold(own, |m, (e, c)| m.max(e * c));
+        let graph = weighted(&[1., 3., 2.], &[(0, 1, 1.), (0, 2, 2.)]);
+        assert_eq!(propagate(&graph, &max), vec![4., 3., 2.]);
+    }
+
+    #[test]
+    fn cycles_terminate() {
+        let graph = weighted(&[1., 1.], &[(0, 1, 1.), (1, 0, 1.)]);
+        assert_eq!(propagate(&graph, &WeightedSum), vec![2., 1.]);
+    }
+
+    #[test]
+    fn open_leaves_are_counted_once_per_subtree() {
+        let mut graph = weighted(&[0.; 5], &[(0, 1, 1.), (0, 2, 1.), (1, 3, 1.), (2, 3, 1.), (2, 4, 1.)]);
+        graph[NodeIndex::new(4)].plugged = true;
+        assert_eq!(open_leaves(&graph), vec![1, 1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn back_edges_break_every_cycle() {
+        // 0 -> 1 -> 2 -> 0 and 2 -> 3 -> 1
+        let mut graph = weighted(&[0.; 4], &[(0, 1, 1.), (1, 2, 1.), (2, 0, 1.), (2, 3, 1.), (3, 1, 1.)]);
+        let closing = back_edges(&graph);
+        assert_eq!(closing, vec![EdgeIndex::new(2), EdgeIndex::new(4)]);
+        graph.retain_edges(|_, e| !closing.contains(&e));
+        assert!(cycles(&graph).is_empty());
+    }
+
+    #[test]
+    fn open_leaves_reach_through_cycles() {
+        // 0 -> 1 -> 2 -> 0 with 2 -> 3 and 1 -> 4
+        let graph = weighted(&[0.; 5], &[(0, 1, 1.), (1, 2, 1.), (2, 0, 1.), (2, 3, 1.), (1, 4, 1.)]);
+        assert_eq!(open_leaves(&graph), vec![2, 2, 2, 1, 1]);
+    }
+
+    #[test]
+    fn open_goals_keep_their_ancestors() {
+        let mut graph = weighted(&[0.; 4], &[(0, 1, 1.), (0, 2, 1.), (2, 3, 1.)]);
+        for goal in graph.node_weights_mut() {
+            goal.plugged = true;
+        }
+        graph[NodeIndex::new(3)].plugged = false;
+        assert_eq!(open_with_ancestors(&graph), vec![true, false, true, true]);
+    }
+
+    #[test]
+    fn cycles_are_found_per_component() {
+        let graph = weighted(&[0.; 6], &[(0, 1, 1.), (1, 2, 1.), (2, 0, 1.), (2, 3, 1.), (4, 5, 1.), (5, 4, 1.)]);
+        let mut found: Vec<Vec<usize>> = cycles(&graph)
+            .into_iter()
+            .map(|c| {
+                let mut ids: Vec<usize> = c.into_iter().map(|i| i.index()).collect();
+                ids.sort();
+                ids
+            })
+            .collect();
+        found.sort();
+        assert_eq!(found, vec![vec![0, 1, 2], vec![4, 5]]);
+        assert_eq!(cycle_members(&graph), vec![true, true, true, false, true, true]);
+    }
+
+    #[test]
+    fn only_back_edges_close_cycles() {
+        let graph = weighted(&[0.; 3], &[(0, 1, 1.), (1, 2, 1.)]);
+        let (a, c) = (NodeIndex::new(0), NodeIndex::new(2));
+        assert!(closes_cycle(&graph, c, a));
+        assert!(!closes_cycle(&graph, a, c));
+    }
+
+    #[test]
+    fn lineage_follows_edge_direction() {
+        let graph = weighted(&[0.; 5], &[(0, 1, 1.), (1, 2, 1.), (1, 3, 1.), (4, 3, 1.)]);
+        let mid = NodeIndex::new(1);
+        assert_eq!(ancestors(&graph, mid), vec![true, false, false, false, false]);
+        assert_eq!(descendants(&graph, mid), vec![false, false, true, true, false]);
+    }
+
+    #[test]
+    fn shares_are_relative_to_largest() {
+        assert_eq!(shares(&[2., -4., 1.]), vec![0.5, 1., 0.25]);
+        assert_eq!(shares(&[0., 0.]), vec![0.5, 0.5]);
+    }
+}
@@ -0,0 +1,80 @@
+use super::analysis;
+use super::{ConsensusEdge, ConsensusGoal};
+use petgraph::prelude::*;
+use std::collections::HashSet;
+
+/// What the graph looks like with some goals folded up.
+#[derive(Debug, Default)]
+pub struct Folding {
+    /// Goals inside a collapsed goal.
+    pub hidden: Vec<bool>,
+    /// For each hidden goal, the visible collapsed goal standing in for it.
+    pub surrogate: Vec<Option<NodeIndex>>,
+    /// For each visible collapsed goal, how many goals it hides.
+    pub inside: Vec<usize>,
+}
+
+/// Folds every goal whose id is in `collapsed` over its descendants.
+pub fn fold(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, collapsed: &HashSet<usize>) -> Folding {
+    let count = graph.node_count();
+    let folded: Vec<NodeIndex> = graph.node_indices().filter(|i| collapsed.contains(&graph[*i].id)).collect();
+    let under: Vec<Vec<bool>> = folded.iter().map(|idx| analysis::descendants(graph, *idx)).collect();
+
+    let mut hidden = vec![false; count];
+    for below in &under {
+        for (i, is_below) in below.iter().enumerate() {
+            hidden[i] |= is_below;
+        }
+    }
+
+    let mut surrogate = vec![None; count];
+    let mut inside = vec![0; count];
+    // only the outermost folds show, so they take in everything below them
+    for (idx, below) in folded.iter().zip(&under).filter(|(idx, _)| !hidden[idx.index()]) {
+        for (i, _) in below.iter().enumerate().filter(|(_, b)| **b) {
+            surrogate[i].get_or_insert(*idx);
+            inside[idx.index()] += 1;
+        }
+    }
+
+    Folding { hidden, surrogate, inside }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::fixture;
+    use super::*;
+
+    fn fold_ids(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, ids: &[usize]) -> Folding {
+        fold(graph, &ids.iter().copied().collect())
+    }
+
+    #[test]
+    fn hides_what_is_under_a_collapsed_goal() {
+        // 0 -> 1 -> 2, 0 -> 3
+        let graph = fixture(4, &[(0, 1), (1, 2), (0, 3)]);
+        let folding = fold_ids(&graph, &[1]);
+        assert_eq!(folding.hidden, vec![false, false, true, false]);
+        assert_eq!(folding.surrogate, vec![None, None, Some(NodeIndex::new(1)), None]);
+        assert_eq!(folding.inside, vec![0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn outer_folds_take_in_inner_ones() {
+        let graph = fixture(4, &[(0, 1), (1, 2), (0, 3)]);
+        let folding = fold_ids(&graph, &[0, 1]);
+        assert_eq!(folding.hidden, vec![false, true, true, true]);
+        assert_eq!(folding.surrogate[2], Some(NodeIndex::new(0)));
+        assert_eq!(folding.inside, vec![3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn shared_sub_goals_count_in_every_fold() {
+        // 0 -> 2 <- 1
+        let graph = fixture(3, &[(0, 2), (1, 2)]);
+        let folding = fold_ids(&graph, &[0, 1]);
+        assert_eq!(folding.hidden, vec![false, false, true]);
+        assert_eq!(folding.surrogate[2], Some(NodeIndex::new(0)));
+        assert_eq!(folding.inside, vec![1, 1, 0]);
+    }
+}
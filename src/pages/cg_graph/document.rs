@@ -0,0 +1,267 @@
+use super::analysis;
+use super::forest::{self, Tree};
+use super::{ConsensusEdge, ConsensusGoal};
+use petgraph::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+pub const DOC_VERSION: u32 = 1;
+
+/// Where a goal sat on the canvas when the graph was saved.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Placement {
+    pub id: usize,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// The graph payload as served by `api/graph/cg_graph`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(from = "Payload")]
+pub struct GraphDocument {
+    pub version: u32,
+    #[serde(default)]
+    pub revision: u64,
+    pub goals: Vec<ConsensusGoal>,
+    pub edges: Vec<ConsensusEdge>,
+    /// Where users left the goals. Goals missing here get laid out. Files keep positions beside
+    /// the document, so it is left out when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub positions: Vec<Placement>,
+}
+
+/// Every shape the backend has served a graph in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Payload {
+    Document {
+        #[serde(default)]
+        version: u32,
+        #[serde(default)]
+        revision: u64,
+        goals: Vec<ConsensusGoal>,
+        edges: Vec<ConsensusEdge>,
+        #[serde(default)]
+        positions: Vec<Placement>,
+    },
+    /// Before documents were versioned a graph was just its goals and edges.
+    Legacy(Vec<ConsensusGoal>, Vec<ConsensusEdge>),
+}
+
+impl From<Payload> for GraphDocument {
+    fn from(payload: Payload) -> Self {
+        match payload {
+            Payload::Document { version, revision, goals, edges, positions } => {
+                GraphDocument { version, revision, goals, edges, positions }
+            }
+            Payload::Legacy(goals, edges) => GraphDocument { version: 0, revision: 0, goals, edges, positions: vec![] },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphError {
+    UnsupportedVersion(u32),
+    DuplicateGoal(usize),
+    DuplicateEdge(usize),
+    DanglingEdge { edge: usize, goal: usize },
+    SelfLoop { edge: usize, goal: usize },
+    MissingRoot { goal: usize },
+    UnknownGoal(usize),
+    UnknownEdge(usize),
+    Cycle { goals: Vec<usize> },
+    ClosesCycle { edge: usize, left: usize, right: usize },
+    /// The backend turned down an edit.
+    Refused(String),
+    /// The backend could not hand over the graph.
+    Unreachable(String),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use GraphError::*;
+        match self {
+            UnsupportedVersion(v) => write!(f, "document version {} is newer than {}", v, DOC_VERSION),
+            DuplicateGoal(id) => write!(f, "goal {} appears more than once, keeping the first", id),
+            DuplicateEdge(id) => write!(f, "edge {} appears more than once, keeping the first", id),
+            DanglingEdge { edge, goal } => write!(f, "edge {} points at missing goal {}", edge, goal),
+            SelfLoop { edge, goal } => write!(f, "edge {} loops goal {} onto itself", edge, goal),
+            MissingRoot { goal } => write!(f, "the tree holding goal {} has no root", goal),
+            UnknownGoal(id) => write!(f, "there is no goal {}", id),
+            UnknownEdge(id) => write!(f, "there is no edge {}", id),
+            Cycle { goals } => write!(f, "goals {:?} form a cycle", goals),
+            ClosesCycle { edge, left, right } => {
+                write!(f, "edge {} from goal {} to {} would close a cycle", edge, left, right)
+            }
+            Refused(why) => write!(f, "the backend refused an edit: {}", why),
+            Unreachable(why) => write!(f, "the graph could not be fetched: {}", why),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<GraphError>,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// What survives validation: everything that could be placed in the graph, plus what could not.
+pub struct ValidGraph {
+    pub graph: DiGraph<ConsensusGoal, ConsensusEdge>,
+    pub trees: Vec<Tree>,
+    pub report: ValidationReport,
+}
+
+impl GraphDocument {
+    pub fn new(goals: Vec<ConsensusGoal>, edges: Vec<ConsensusEdge>) -> Self {
+        Self {
+            version: DOC_VERSION,
+            revision: 0,
+            goals,
+            edges,
+            positions: vec![],
+        }
+    }
+
+    /// The document `graph` would have been loaded from.
+    pub fn from_graph(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, revision: u64) -> Self {
+        let edges = graph
+            .raw_edges()
+            .iter()
+            .map(|e| ConsensusEdge {
+                left: graph[e.source()].id,
+                right: graph[e.target()].id,
+                ..e.weight.clone()
+            })
+            .collect();
+        Self {
+            version: DOC_VERSION,
+            revision,
+            goals: graph.raw_nodes().iter().map(|n| n.weight.clone()).collect(),
+            edges,
+            positions: vec![],
+        }
+    }
+
+    /// Builds the graph, dropping anything that would leave it inconsistent and reporting why.
+    pub fn validate(self) -> ValidGraph {
+        let mut report = ValidationReport::default();
+        if self.version > DOC_VERSION {
+            report.errors.push(GraphError::UnsupportedVersion(self.version));
+        }
+
+        let mut graph = DiGraph::with_capacity(self.goals.len(), self.edges.len());
+        let mut idx_map: HashMap<usize, NodeIndex> = HashMap::with_capacity(self.goals.len());
+        for goal in self.goals {
+            if idx_map.contains_key(&goal.id) {
+                report.errors.push(GraphError::DuplicateGoal(goal.id));
+                continue;
+            }
+            let id = goal.id;
+            idx_map.insert(id, graph.add_node(goal));
+        }
+
+        let mut seen_edges = HashSet::with_capacity(self.edges.len());
+        for edge in self.edges {
+            if !seen_edges.insert(edge.id) {
+                report.errors.push(GraphError::DuplicateEdge(edge.id));
+                continue;
+            }
+            if edge.left == edge.right {
+                report.errors.push(GraphError::SelfLoop { edge: edge.id, goal: edge.left });
+                continue;
+            }
+            let (left, right) = match (idx_map.get(&edge.left), idx_map.get(&edge.right)) {
+                (Some(l), Some(r)) => (*l, *r),
+                (None, _) => {
+                    report.errors.push(GraphError::DanglingEdge { edge: edge.id, goal: edge.left });
+                    continue;
+                }
+                (_, None) => {
+                    report.errors.push(GraphError::DanglingEdge { edge: edge.id, goal: edge.right });
+                    continue;
+                }
+            };
+            graph.add_edge(left, right, edge);
+        }
+
+        // no goal marked as root and nothing without parents: a cycle, or a cycle feeding the rest
+        let trees = forest::trees(&graph);
+        for tree in trees.iter().filter(|t| t.roots.is_empty()) {
+            report.errors.push(GraphError::MissingRoot { goal: graph[tree.order[0]].id });
+        }
+
+        for cycle in analysis::cycles(&graph) {
+            let goals = cycle.into_iter().map(|i| graph[i].id).collect();
+            report.errors.push(GraphError::Cycle { goals });
+        }
+
+        ValidGraph { graph, trees, report }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goal(id: usize) -> ConsensusGoal {
+        ConsensusGoal { id, ..Default::default() }
+    }
+
+    fn edge(id: usize, left: usize, right: usize) -> ConsensusEdge {
+        ConsensusEdge { id, left, right, ..Default::default() }
+    }
+
+    fn errors(goals: Vec<ConsensusGoal>, edges: Vec<ConsensusEdge>) -> Vec<GraphError> {
+        GraphDocument::new(goals, edges).validate().report.errors
+    }
+
+    #[test]
+    fn reads_unversioned_and_legacy_payloads() {
+        let doc: GraphDocument = serde_json::from_str(r#"{"goals": [], "edges": []}"#).unwrap();
+        assert_eq!(doc.version, 0);
+        let legacy = r#"[[{"id": 0, "plugged": false, "st8mnt": "", "weight": 1.0}], []]"#;
+        let doc: GraphDocument = serde_json::from_str(legacy).unwrap();
+        assert_eq!((doc.goals.len(), doc.edges.len()), (1, 0));
+        assert!(doc.validate().report.is_clean());
+    }
+
+    #[test]
+    fn drops_duplicate_ids_keeping_the_first() {
+        let first = ConsensusGoal { st8mnt: "first".to_string(), ..goal(0) };
+        let valid = GraphDocument::new(vec![first, goal(0), goal(1)], vec![edge(0, 0, 1), edge(0, 1, 0)]).validate();
+        assert_eq!(valid.report.errors, vec![GraphError::DuplicateGoal(0), GraphError::DuplicateEdge(0)]);
+        assert_eq!(valid.graph.node_count(), 2);
+        assert_eq!(valid.graph.edge_count(), 1);
+        assert_eq!(valid.graph[NodeIndex::new(0)].st8mnt, "first");
+    }
+
+    #[test]
+    fn drops_dangling_edges_and_self_loops() {
+        let found = errors(vec![goal(0), goal(1)], vec![edge(0, 0, 1), edge(1, 0, 7), edge(2, 1, 1)]);
+        assert_eq!(
+            found,
+            vec![GraphError::DanglingEdge { edge: 1, goal: 7 }, GraphError::SelfLoop { edge: 2, goal: 1 }]
+        );
+    }
+
+    #[test]
+    fn falls_back_to_parentless_goals_for_roots() {
+        let valid = GraphDocument::new(vec![goal(0), goal(1), goal(2)], vec![edge(0, 0, 1), edge(1, 0, 2)]).validate();
+        assert!(valid.report.is_clean());
+        assert_eq!(valid.trees[0].roots, vec![NodeIndex::new(0)]);
+    }
+
+    #[test]
+    fn reports_trees_fed_by_a_cycle_as_rootless() {
+        // 0 <-> 1 feeding 2: every goal has a parent
+        let found = errors(vec![goal(0), goal(1), goal(2)], vec![edge(0, 0, 1), edge(1, 1, 0), edge(2, 1, 2)]);
+        assert!(found.contains(&GraphError::MissingRoot { goal: 0 }));
+        assert!(found.iter().any(|err| matches!(err, GraphError::Cycle { .. })));
+    }
+}
@@ -0,0 +1,464 @@
+use super::io::{GraphFile, Placement};
+use super::{ConsensusEdge, ConsensusGoal, GraphDocument};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotError(pub String);
+
+impl fmt::Display for DotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Graphviz points per canvas pixel, at 72 points and 96 pixels to the inch.
+const POINTS_PER_PX: f64 = 0.75;
+
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Writes the graph as a Graphviz digraph. Goals are named by id and carry their fields, and
+/// saved positions, as attributes. Weights go in `cg_weight`, since Graphviz reads `weight` as an
+/// integer layout hint, and positions are in points with y pointing up, as Graphviz has them.
+pub fn write(file: &GraphFile) -> String {
+    let positions: HashMap<usize, &Placement> = file.positions.iter().map(|p| (p.id, p)).collect();
+    let mut out = String::from("digraph cg_graph {\n");
+    for goal in &file.graph.goals {
+        write!(
+            out,
+            "    {} [label={}, cg_weight={}, plugged={}, root={}",
+            goal.id,
+            quote(&goal.st8mnt),
+            goal.weight,
+            goal.plugged,
+            goal.root
+        )
+        .unwrap();
+        if let Some(place) = positions.get(&goal.id) {
+            write!(out, ", pos=\"{},{}\"", place.x * POINTS_PER_PX, -place.y * POINTS_PER_PX).unwrap();
+        }
+        out.push_str("];\n");
+    }
+    for edge in &file.graph.edges {
+        writeln!(
+            out,
+            "    {} -> {} [id={}, label={}, cg_weight={}];",
+            edge.left,
+            edge.right,
+            edge.id,
+            quote(&edge.label),
+            edge.weight
+        )
+        .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Id(String),
+    Arrow,
+    Line,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Eq,
+    Comma,
+    Semi,
+    Colon,
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+fn lex(src: &str) -> Result<Vec<Token>, DotError> {
+    let mut tokens = vec![];
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '#' => {
+                while chars.peek().map_or(false, |c| *c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().map_or(false, |c| *c != '\n') {
+                    chars.next();
+                }
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if prev == '*' => break,
+                        Some(c) => prev = c,
+                        None => return Err(DotError("unclosed comment".to_string())),
+                    }
+                }
+                continue;
+            }
+            '-' if chars.peek() == Some(&'>') => {
+                chars.next();
+                Token::Arrow
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                Token::Line
+            }
+            '{' => Token::LBrace,
+            '}' => Token::RBrace,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            '=' => Token::Eq,
+            ',' => Token::Comma,
+            ';' => Token::Semi,
+            ':' => Token::Colon,
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => s.push('\n'),
+                            Some(c @ '"') | Some(c @ '\\') => s.push(c),
+                            Some(c) => {
+                                s.push('\\');
+                                s.push(c);
+                            }
+                            None => return Err(DotError("unclosed string".to_string())),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err(DotError("unclosed string".to_string())),
+                    }
+                }
+                Token::Id(s)
+            }
+            c if c == '-' || is_id_char(c) => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.peek().copied().filter(|c| is_id_char(*c)) {
+                    s.push(c);
+                    chars.next();
+                }
+                Token::Id(s)
+            }
+            c => return Err(DotError(format!("unexpected '{}'", c))),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+type Attrs = HashMap<String, String>;
+
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn id(&mut self) -> Result<String, DotError> {
+        match self.next() {
+            Some(Token::Id(id)) => Ok(id),
+            other => Err(DotError(format!("expected a name, found {:?}", other))),
+        }
+    }
+
+    fn expect(&mut self, want: Token) -> Result<(), DotError> {
+        match self.next() {
+            Some(ref got) if *got == want => Ok(()),
+            other => Err(DotError(format!("expected {:?}, found {:?}", want, other))),
+        }
+    }
+
+    fn keyword(&self, offset: usize, kw: &str) -> bool {
+        match self.tokens.get(self.at + offset) {
+            Some(Token::Id(id)) => id.eq_ignore_ascii_case(kw),
+            _ => false,
+        }
+    }
+
+    /// Any number of `[a=b, ...]` lists.
+    fn attrs(&mut self) -> Result<Attrs, DotError> {
+        let mut attrs = Attrs::new();
+        while self.peek() == Some(&Token::LBracket) {
+            self.next();
+            loop {
+                match self.peek() {
+                    Some(Token::RBracket) => {
+                        self.next();
+                        break;
+                    }
+                    Some(Token::Comma) | Some(Token::Semi) => {
+                        self.next();
+                    }
+                    _ => {
+                        let key = self.id()?;
+                        self.expect(Token::Eq)?;
+                        attrs.insert(key, self.id()?);
+                    }
+                }
+            }
+        }
+        Ok(attrs)
+    }
+
+    /// A node name, dropping any `:port`.
+    fn node(&mut self) -> Result<String, DotError> {
+        let name = self.id()?;
+        while self.peek() == Some(&Token::Colon) {
+            self.next();
+            self.id()?;
+        }
+        Ok(name)
+    }
+}
+
+#[derive(Default)]
+struct Collected {
+    names: Vec<String>,
+    nodes: HashMap<String, Attrs>,
+    edges: Vec<(String, String, Attrs)>,
+}
+
+impl Collected {
+    fn node(&mut self, name: &str) -> &mut Attrs {
+        if !self.nodes.contains_key(name) {
+            self.names.push(name.to_string());
+        }
+        self.nodes.entry(name.to_string()).or_default()
+    }
+}
+
+fn statements(p: &mut Parser) -> Result<Collected, DotError> {
+    if p.keyword(0, "strict") {
+        p.next();
+    }
+    if p.keyword(0, "graph") {
+        return Err(DotError("only directed graphs (digraph) can be loaded".to_string()));
+    }
+    if !p.keyword(0, "digraph") {
+        return Err(DotError("expected digraph".to_string()));
+    }
+    p.next();
+    if let Some(Token::Id(_)) = p.peek() {
+        p.next();
+    }
+    p.expect(Token::LBrace)?;
+
+    let mut found = Collected::default();
+    loop {
+        match p.peek() {
+            None => return Err(DotError("missing closing }".to_string())),
+            Some(Token::RBrace) => break,
+            Some(Token::Semi) => {
+                p.next();
+            }
+            Some(Token::LBrace) => return Err(DotError("subgraphs are not supported".to_string())),
+            Some(Token::Id(_)) if p.keyword(0, "subgraph") => {
+                return Err(DotError("subgraphs are not supported".to_string()))
+            }
+            Some(Token::Id(_))
+                if ["graph", "node", "edge"].iter().any(|kw| p.keyword(0, kw))
+                    && p.tokens.get(p.at + 1) == Some(&Token::LBracket) =>
+            {
+                // defaults for the whole graph say nothing about any one goal
+                p.next();
+                p.attrs()?;
+            }
+            Some(Token::Id(_)) if p.tokens.get(p.at + 1) == Some(&Token::Eq) => {
+                p.next();
+                p.next();
+                p.id()?;
+            }
+            Some(Token::Id(_)) => {
+                let mut chain = vec![p.node()?];
+                loop {
+                    match p.peek() {
+                        Some(Token::Arrow) => {
+                            p.next();
+                            chain.push(p.node()?);
+                        }
+                        Some(Token::Line) => return Err(DotError("undirected edge in a digraph".to_string())),
+                        _ => break,
+                    }
+                }
+                let attrs = p.attrs()?;
+                if chain.len() == 1 {
+                    found.node(&chain[0]).extend(attrs);
+                } else {
+                    for pair in chain.windows(2) {
+                        found.node(&pair[0]);
+                        found.node(&pair[1]);
+                        found.edges.push((pair[0].clone(), pair[1].clone(), attrs.clone()));
+                    }
+                }
+            }
+            Some(other) => return Err(DotError(format!("unexpected {:?}", other))),
+        }
+    }
+    Ok(found)
+}
+
+fn number<T: std::str::FromStr + Default>(attrs: &Attrs, key: &str) -> T {
+    attrs.get(key).and_then(|v| v.parse().ok()).unwrap_or_default()
+}
+
+/// Our own `cg_weight`, or failing that a hand-written `weight`.
+fn weight(attrs: &Attrs) -> f32 {
+    let key = if attrs.contains_key("cg_weight") { "cg_weight" } else { "weight" };
+    number(attrs, key)
+}
+
+fn flag(attrs: &Attrs, key: &str) -> bool {
+    attrs.get(key).map_or(false, |v| v.eq_ignore_ascii_case("true"))
+}
+
+fn position(id: usize, attrs: &Attrs) -> Option<Placement> {
+    let pos = attrs.get("pos")?.trim_end_matches('!');
+    let mut xy = pos.split(',').map(|v| v.trim().parse::<f64>());
+    match (xy.next(), xy.next()) {
+        (Some(Ok(x)), Some(Ok(y))) => Some(Placement { id, x: x / POINTS_PER_PX, y: -y / POINTS_PER_PX }),
+        _ => None,
+    }
+}
+
+/// Reads a Graphviz digraph. Goals keep numeric names as their ids; if any name is not a number,
+/// every goal is numbered in the order it first appears. Unknown attributes are ignored.
+pub fn parse(src: &str) -> Result<GraphFile, DotError> {
+    let mut p = Parser { tokens: lex(src)?, at: 0 };
+    let found = statements(&mut p)?;
+
+    let numeric: Option<Vec<usize>> = found.names.iter().map(|n| n.parse().ok()).collect();
+    let ids: HashMap<&str, usize> = match numeric {
+        Some(numbers) => found.names.iter().map(String::as_str).zip(numbers).collect(),
+        None => found.names.iter().enumerate().map(|(i, n)| (n.as_str(), i)).collect(),
+    };
+
+    let mut file = GraphFile::default();
+    for name in &found.names {
+        let (id, attrs) = (ids[name.as_str()], &found.nodes[name]);
+        file.graph.goals.push(ConsensusGoal {
+            id,
+            root: flag(attrs, "root"),
+            plugged: flag(attrs, "plugged"),
+            st8mnt: attrs.get("label").cloned().unwrap_or_else(|| name.clone()),
+            weight: weight(attrs),
+        });
+        file.positions.extend(position(id, attrs));
+    }
+
+    let taken: HashSet<usize> = found.edges.iter().filter_map(|(_, _, a)| a.get("id")?.parse().ok()).collect();
+    let mut fresh = (0..).filter(|i| !taken.contains(i));
+    for (left, right, attrs) in &found.edges {
+        let id = match attrs.get("id").and_then(|v| v.parse().ok()) {
+            Some(id) => id,
+            None => fresh.next().expect("unbounded range"),
+        };
+        file.graph.edges.push(ConsensusEdge {
+            id,
+            label: attrs.get("label").cloned().unwrap_or_default(),
+            left: ids[left.as_str()],
+            right: ids[right.as_str()],
+            weight: weight(attrs),
+        });
+    }
+    file.graph = GraphDocument::new(file.graph.goals, file.graph.edges);
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_goals_edges_and_positions() {
+        let file = GraphFile {
+            graph: GraphDocument::new(
+                vec![
+                    ConsensusGoal { id: 3, root: true, st8mnt: "say \"hi\"".to_string(), weight: 1.5, ..Default::default() },
+                    ConsensusGoal { id: 7, plugged: true, st8mnt: "two\nlines".to_string(), ..Default::default() },
+                ],
+                vec![ConsensusEdge { id: 2, label: "needs".to_string(), left: 3, right: 7, weight: 0.5 }],
+            ),
+            positions: vec![Placement { id: 7, x: 10., y: 20.5 }],
+        };
+        let back = parse(&write(&file)).unwrap();
+        assert_eq!(back.graph.goals.len(), 2);
+        let (a, b) = (&back.graph.goals[0], &back.graph.goals[1]);
+        assert_eq!((a.id, a.root, a.plugged, a.st8mnt.as_str(), a.weight), (3, true, false, "say \"hi\"", 1.5));
+        assert_eq!((b.id, b.root, b.plugged, b.st8mnt.as_str()), (7, false, true, "two\nlines"));
+        let e = &back.graph.edges[0];
+        assert_eq!((e.id, e.left, e.right, e.label.as_str(), e.weight), (2, 3, 7, "needs", 0.5));
+        assert_eq!((back.positions[0].id, back.positions[0].x, back.positions[0].y), (7, 10., 20.5));
+    }
+
+    #[test]
+    fn reads_hand_written_graphviz() {
+        let src = r#"
+            // made in an editor
+            strict digraph G {
+                rankdir=LR;
+                node [shape=box];
+                a [label="Fund it"];
+                a -> b -> c [weight=2];
+                /* c has no label */
+            }
+        "#;
+        let file = parse(src).unwrap();
+        let names: Vec<&str> = file.graph.goals.iter().map(|g| g.st8mnt.as_str()).collect();
+        assert_eq!(names, vec!["Fund it", "b", "c"]);
+        let ends: Vec<(usize, usize, f32)> = file.graph.edges.iter().map(|e| (e.left, e.right, e.weight)).collect();
+        assert_eq!(ends, vec![(0, 1, 2.), (1, 2, 2.)]);
+    }
+
+    #[test]
+    fn writes_graphviz_weights_and_positions() {
+        let file = GraphFile {
+            graph: GraphDocument::new(
+                vec![ConsensusGoal { id: 1, weight: 0.5, ..Default::default() }],
+                vec![ConsensusEdge { id: 0, left: 1, right: 1, weight: 0.25, ..Default::default() }],
+            ),
+            positions: vec![Placement { id: 1, x: 40., y: 100. }],
+        };
+        let src = write(&file);
+        assert!(src.contains("cg_weight=0.5") && src.contains("cg_weight=0.25"));
+        assert!(!src.contains(" weight="));
+        assert!(src.contains("pos=\"30,-75\""));
+    }
+
+    #[test]
+    fn refuses_undirected_graphs() {
+        assert!(parse("graph { a -- b }").is_err());
+    }
+}
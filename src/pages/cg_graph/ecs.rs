@@ -1,39 +1,168 @@
-use crate::ametheed::Interactable;
-use crate::ametheed::UiTransform;
-use crate::ametheed::ui::button::UiButtonActionRetrigger;
-use crate::ametheed::UiText;
-use crate::ametheed::ui::colored_box::UiColorBox;
-use specs::{World, WorldExt};
+use crate::components::{Dimension, Hidden, Interactable, Pinned, Pos};
+use specs::prelude::*;
 pub mod components;
-mod systems;
+pub mod systems;
 use components::*;
 pub use components::Color;
-use crate::ametheed::UiButton;
 
 #[derive(Default)]
-pub struct State {
-    pub inner: World,
+pub struct DTime(pub std::time::Duration);
+
+/// The fastest anything moved on the last step, in px/s.
+#[derive(Debug, Default)]
+pub struct Motion(pub f32);
+
+/// Below this nothing visibly moves any more.
+const SETTLED: f32 = 5.;
+
+/// Longest step we integrate, so a stalled tab does not fling everything off the canvas.
+const MAX_STEP: f64 = 0.05;
+
+pub fn register(world: &mut World) {
+    world.register::<Position>();
+    world.register::<Velocity>();
+    world.insert(DTime::default());
+    world.insert(Motion::default());
 }
 
-impl std::fmt::Debug for State {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.debug_struct("World").finish()
+/// Puts every visible goal under the force layout, and hands the ones that are dragged or pinned
+/// over to wherever they are on the canvas. So do goals moved since the last step by anything
+/// else, such as undo or pushes on drop, so the layout does not snap them back.
+fn embody(world: &mut World) {
+    let (ents, poss, dims, states, pinned, hidden, mut positions, mut velocities) = world.system_data::<(
+        Entities,
+        ReadStorage<Pos>,
+        ReadStorage<Dimension>,
+        ReadStorage<Interactable>,
+        ReadStorage<Pinned>,
+        ReadStorage<Hidden>,
+        WriteStorage<Position>,
+        WriteStorage<Velocity>,
+    )>();
+    for (ent, pos, _, state) in (&ents, &poss, &dims, states.maybe()).join() {
+        let held = pinned.contains(ent) || matches!(state, Some(Interactable::MouseDown(_, _)));
+        if hidden.contains(ent) {
+            positions.remove(ent);
+            velocities.remove(ent);
+        } else if held || positions.get(ent).map_or(true, |p| p.x != pos.x as f32 || p.y != pos.y as f32) {
+            positions.insert(ent, Position { x: pos.x as f32, y: pos.y as f32 }).unwrap();
+            velocities.insert(ent, Velocity { x: 0., y: 0. }).unwrap();
+        }
     }
 }
-impl State {
-    pub fn init() -> State {
-        let mut world = World::new();
-        world.register::<Position>();
-        world.register::<Velocity>();
-        world.register::<Renderable>();
-        world.register::<UiColorBox>();
-        world.register::<UiText>();
-        world.register::<UiButtonActionRetrigger>();
-        world.register::<UiTransform>();
-        world.register::<Interactable>();
-        // world.register::<Hovered>();
-        State { inner: world }
+
+/// Runs one step of the force layout over `seconds`, and says whether anything is still moving.
+pub fn step(world: &mut World, seconds: f64) -> bool {
+    embody(world);
+    world.write_resource::<DTime>().0 = std::time::Duration::from_secs_f64(seconds.min(MAX_STEP));
+    systems::Forces.run_now(world);
+    systems::UpdatePos.run_now(world);
+    systems::WritePos.run_now(world);
+    world.maintain();
+    world.read_resource::<Motion>().0 > SETTLED
+}
+
+/// Takes every goal back out of the force layout.
+pub fn release(world: &mut World) {
+    world.write_storage::<Position>().clear();
+    world.write_storage::<Velocity>().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::history::History;
+    use super::super::{patch, test_util, world_index, GraphWorldIndex};
+    use petgraph::graph::NodeIndex;
+
+    fn world() -> World {
+        let mut world = test_util::world();
+        world.register::<Pinned>();
+        register(&mut world);
+        world
+    }
+
+    fn goal(world: &mut World, x: f64, y: f64) -> Entity {
+        world.create_entity().with(Pos { x, y }).with(Dimension { w: 50., h: 50. }).build()
+    }
+
+    fn distance(world: &World, ent: Entity, to: Pos) -> f64 {
+        let (x, y) = pos(world, ent);
+        ((x - to.x).powi(2) + (y - to.y).powi(2)).sqrt()
+    }
+
+    fn pos(world: &World, ent: Entity) -> (f64, f64) {
+        let pos = world.read_storage::<Pos>().get(ent).copied().unwrap();
+        (pos.x, pos.y)
+    }
+
+    #[test]
+    fn clamps_long_steps() {
+        let mut world = world();
+        goal(&mut world, 0., 0.);
+        step(&mut world, 10.);
+        assert_eq!(world.read_resource::<DTime>().0.as_secs_f64(), MAX_STEP);
+    }
+
+    #[test]
+    fn settles_once_nothing_moves() {
+        let mut world = world();
+        goal(&mut world, 0., 0.);
+        assert!(!step(&mut world, 0.02));
+        // close enough to push each other apart
+        goal(&mut world, 10., 0.);
+        assert!(step(&mut world, 0.02));
+        assert!(world.read_resource::<Motion>().0 > SETTLED);
+    }
+
+    #[test]
+    fn pinned_goals_stay_put() {
+        let mut world = world();
+        let pinned = goal(&mut world, 0., 0.);
+        let free = goal(&mut world, 10., 0.);
+        world.write_storage::<Pinned>().insert(pinned, Pinned).unwrap();
+        for _ in 0..5 {
+            step(&mut world, 0.02);
+        }
+        assert_eq!(pos(&world, pinned), (0., 0.));
+        assert!(pos(&world, free).0 > 10.);
+    }
+
+    #[test]
+    fn undone_moves_stick_while_forces_run() {
+        let (mut world, graph) = test_util::drawn(2, &[]);
+        world.register::<Pinned>();
+        register(&mut world);
+        let idx = NodeIndex::new(0);
+        let ent = world.read_resource::<GraphWorldIndex>().node_entity(idx).unwrap();
+        world_index::set_node_pos(&mut world, NodeIndex::new(1), Pos { x: 400., y: 400. });
+        let start = Pos { x: 0., y: 0. };
+        world_index::set_node_pos(&mut world, idx, start);
+        step(&mut world, 0.02);
+
+        let mut history = History::default();
+        history.begin_drag(vec![(0, start)]);
+        world_index::set_node_pos(&mut world, idx, Pos { x: 200., y: 0. });
+        assert!(history.end_drag(&world, &graph));
+        step(&mut world, 0.02);
+
+        // as `run_step` does it
+        for (id, to) in history.undo().unwrap().moves {
+            let idx = patch::goal_index(&graph, id).unwrap();
+            world_index::set_node_pos(&mut world, idx, to);
+        }
+        step(&mut world, 0.02);
+        assert!(distance(&world, ent, start) < 5.);
+    }
+
+    #[test]
+    fn release_takes_goals_out_of_the_layout() {
+        let mut world = world();
+        let ent = goal(&mut world, 0., 0.);
+        step(&mut world, 0.02);
+        assert!(world.read_storage::<Position>().contains(ent));
+        release(&mut world);
+        assert!(!world.read_storage::<Position>().contains(ent));
+        assert!(!world.read_storage::<Velocity>().contains(ent));
     }
 }
-#[derive(Default)]
-pub struct DTime(std::time::Duration);
@@ -1,4 +1,4 @@
-use specs::{Component, VecStorage, WorldExt};
+use specs::{Component, VecStorage};
 
 #[derive(Component, Clone, Copy, Debug, Default, Deserialize, Serialize)]
 #[storage(VecStorage)]
@@ -1,6 +1,18 @@
+use crate::components::{Edge, Interactable, Pinned, Pos};
 use crate::pages::cg_graph::ecs::components::{Position, Velocity};
-use crate::pages::cg_graph::ecs::DTime;
-use specs::{Join, Read, ReadStorage, System, WriteStorage,};
+use crate::pages::cg_graph::ecs::{DTime, Motion};
+use specs::{Entities, Entity, Join, Read, ReadStorage, System, Write, WriteStorage,};
+use std::collections::HashMap;
+
+/// Rest length of an edge's spring, in px.
+const LENGTH: f32 = 150.;
+/// Spring stiffness, per s².
+const SPRING: f32 = 4.;
+/// Repulsion between any two goals, in px³/s².
+const REPULSION: f32 = 400_000.;
+/// Fraction of speed lost per second.
+const FRICTION: f32 = 3.;
+const MAX_SPEED: f32 = 1_000.;
 
 pub struct MousePos {
     pub x: f64,
@@ -24,6 +36,86 @@ impl<'a> System<'a> for UpdatePos {
     }
 }
 
+/// Springs along edges, repulsion between every pair of goals, and friction, applied to the
+/// velocities. Goals that are dragged or pinned do not move.
+pub struct Forces;
+impl<'a> System<'a> for Forces {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, DTime>,
+        Write<'a, Motion>,
+        ReadStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Edge>,
+        ReadStorage<'a, Pinned>,
+        ReadStorage<'a, Interactable>,
+    );
+    fn run(&mut self, (ents, delta, mut motion, pos, mut vel, edges, pinned, states): Self::SystemData) {
+        let bodies: Vec<(Entity, f32, f32)> = (&ents, &pos).join().map(|(ent, p)| (ent, p.x, p.y)).collect();
+        let slot: HashMap<Entity, usize> = bodies.iter().enumerate().map(|(i, b)| (b.0, i)).collect();
+        let mut force = vec![(0f32, 0f32); bodies.len()];
+        let mut push = |i: usize, j: usize, strength: &dyn Fn(f32) -> f32| {
+            let (mut dx, dy) = (bodies[j].1 - bodies[i].1, bodies[j].2 - bodies[i].2);
+            if dx == 0. && dy == 0. {
+                // on top of each other, pull them apart sideways
+                dx = 1.;
+            }
+            let d = (dx * dx + dy * dy).sqrt();
+            let f = strength(d);
+            force[i].0 += f * dx / d;
+            force[i].1 += f * dy / d;
+            force[j].0 -= f * dx / d;
+            force[j].1 -= f * dy / d;
+        };
+        for i in 0..bodies.len() {
+            for j in i + 1..bodies.len() {
+                push(i, j, &|d| -REPULSION / (d * d).max(1.));
+            }
+        }
+        for edge in edges.join() {
+            if let (Some(i), Some(j)) = (slot.get(&edge.left), slot.get(&edge.right)) {
+                push(*i, *j, &|d| SPRING * (d - LENGTH));
+            }
+        }
+
+        let dt = delta.0.as_secs_f32();
+        let mut fastest = 0f32;
+        for ((ent, _, _), (fx, fy)) in bodies.iter().zip(force) {
+            let v = match vel.get_mut(*ent) {
+                Some(v) => v,
+                None => continue,
+            };
+            let held = pinned.contains(*ent) || matches!(states.get(*ent), Some(Interactable::MouseDown(_, _)));
+            if held {
+                *v = Velocity { x: 0., y: 0. };
+                continue;
+            }
+            let keep = (1. - FRICTION * dt).max(0.);
+            v.x = (v.x + fx * dt) * keep;
+            v.y = (v.y + fy * dt) * keep;
+            let speed = (v.x * v.x + v.y * v.y).sqrt();
+            if speed > MAX_SPEED {
+                v.x *= MAX_SPEED / speed;
+                v.y *= MAX_SPEED / speed;
+            }
+            fastest = fastest.max(speed.min(MAX_SPEED));
+        }
+        motion.0 = fastest;
+    }
+}
+
+/// Copies the force layout's positions onto the canvas.
+pub struct WritePos;
+impl<'a> System<'a> for WritePos {
+    type SystemData = (ReadStorage<'a, Position>, WriteStorage<'a, Pos>);
+    fn run(&mut self, (positions, mut poss): Self::SystemData) {
+        for (p, pos) in (&positions, &mut poss).join() {
+            pos.x = p.x as f64;
+            pos.y = p.y as f64;
+        }
+    }
+}
+
 // pub struct CheckHover;
 // impl<'a> System<'a> for CheckHover {
 //     type SystemData = (
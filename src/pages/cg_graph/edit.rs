@@ -0,0 +1,159 @@
+use super::patch;
+use super::route::{route_edges, EdgeRouting, Rect};
+use super::{ConsensusEdge, ConsensusGoal};
+use crate::components::*;
+use petgraph::prelude::*;
+use specs::prelude::*;
+
+/// Side of the square in a goal's bottom right corner that starts a new edge when dragged.
+pub const HANDLE: f64 = 10.;
+/// How close, in px, a click has to come to an edge to pick it.
+const REACH: f64 = 4.;
+
+fn goals(world: &World) -> Vec<(Entity, Pos, Dimension)> {
+    let (ents, poss, dims, inter, hidden) = (
+        world.entities(),
+        world.read_storage::<Pos>(),
+        world.read_storage::<Dimension>(),
+        world.read_storage::<Interactable>(),
+        world.read_storage::<Hidden>(),
+    );
+    (&ents, &poss, &dims, &inter, !&hidden)
+        .join()
+        .map(|(ent, pos, dim, _, _)| (ent, Pos { x: pos.x, y: pos.y }, Dimension { w: dim.w, h: dim.h }))
+        .collect()
+}
+
+/// The topmost goal under the point, if any.
+pub fn node_at(world: &World, x: f64, y: f64) -> Option<Entity> {
+    goals(world)
+        .into_iter()
+        .rev()
+        .find(|(_, pos, dim)| x > pos.x && x < pos.x + dim.w && y > pos.y && y < pos.y + dim.h)
+        .map(|(ent, _, _)| ent)
+}
+
+/// The edge drawn under the point, if any, routed the way the renderer routes it.
+pub fn edge_at(world: &World, x: f64, y: f64) -> Option<Entity> {
+    let (ents, poss, dims, hidden, edges, rerouted) = (
+        world.entities(),
+        world.read_storage::<Pos>(),
+        world.read_storage::<Dimension>(),
+        world.read_storage::<Hidden>(),
+        world.read_storage::<Edge>(),
+        world.read_storage::<Rerouted>(),
+    );
+    let goals: Vec<(Entity, Rect)> = (&ents, &poss, &dims, !&hidden)
+        .join()
+        .map(|(ent, pos, dim, _)| (ent, Rect::of(pos, dim)))
+        .collect();
+    let links: Vec<(Entity, (Entity, Entity))> = (&ents, &edges)
+        .join()
+        .map(|(ent, edge)| (ent, rerouted.get(ent).map_or((edge.left, edge.right), |r| (r.left, r.right))))
+        .collect();
+    let ends: Vec<(Entity, Entity)> = links.iter().map(|(_, ends)| *ends).collect();
+    let routing = *world.read_resource::<EdgeRouting>();
+    links
+        .iter()
+        .zip(route_edges(&goals, &ends, routing))
+        .find(|(_, routed)| routed.as_ref().map_or(false, |r| r.path.passes_near((x, y), REACH)))
+        .map(|((ent, _), _)| *ent)
+}
+
+/// The goal whose edge handle is under the point, if any.
+pub fn handle_at(world: &World, x: f64, y: f64) -> Option<Entity> {
+    goals(world)
+        .into_iter()
+        .rev()
+        .find(|(_, pos, dim)| {
+            let (hx, hy) = (pos.x + dim.w - HANDLE, pos.y + dim.h - HANDLE);
+            x > hx && x < hx + HANDLE && y > hy && y < hy + HANDLE
+        })
+        .map(|(ent, _, _)| ent)
+}
+
+/// The goal whose collapse toggle, in its top right corner, is under the point, if any. Only goals
+/// with sub-goals, or already collapsed, have one.
+pub fn toggle_at(world: &World, x: f64, y: f64) -> Option<Entity> {
+    let (foldable, collapsed) = (world.read_storage::<Foldable>(), world.read_storage::<Collapsed>());
+    goals(world)
+        .into_iter()
+        .rev()
+        .filter(|(ent, _, _)| foldable.contains(*ent) || collapsed.contains(*ent))
+        .find(|(_, pos, dim)| {
+            let hx = pos.x + dim.w - HANDLE;
+            x > hx && x < hx + HANDLE && y > pos.y && y < pos.y + HANDLE
+        })
+        .map(|(ent, _, _)| ent)
+}
+
+/// Ids are drawn from `0..ID_SPACE`, which fits a wasm `usize`.
+const ID_SPACE: f64 = u32::MAX as f64;
+
+/// An id `taken` says is free. Drawn at random from `draw`, which gives numbers in `0..1`, so that
+/// clients editing the same graph at once do not mint the same ids the way counting up would.
+fn fresh_id(taken: impl Fn(usize) -> bool, mut draw: impl FnMut() -> f64) -> usize {
+    loop {
+        let id = (draw() * ID_SPACE) as usize;
+        if !taken(id) {
+            return id;
+        }
+    }
+}
+
+pub fn next_goal_id(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> usize {
+    fresh_id(|id| patch::goal_index(graph, id).is_some(), js_sys::Math::random)
+}
+
+pub fn next_edge_id(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> usize {
+    fresh_id(|id| patch::edge_index(graph, id).is_some(), js_sys::Math::random)
+}
+
+/// A goal statement being typed into the inline editor. Kept by goal id, since edits coming in
+/// meanwhile can move the goal to another index.
+#[derive(Debug)]
+pub struct Rename {
+    pub id: usize,
+    pub text: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_goals_with_sub_goals_toggle() {
+        use super::super::test_util::drawn;
+        use super::super::{collapse, world_index, GraphWorldIndex};
+        use std::collections::HashSet;
+
+        // 0 -> 1, both drawn at the origin so the topmost, 1, is under the point
+        let (world, graph) = drawn(2, &[(0, 1)]);
+        world_index::apply_folding(&world, &graph, &collapse::fold(&graph, &HashSet::new()));
+        let (x, y) = (world_index::node_dimension().w - HANDLE / 2., HANDLE / 2.);
+        let parent = world.read_resource::<GraphWorldIndex>().node_entity(NodeIndex::new(0));
+        assert_eq!(toggle_at(&world, x, y), parent);
+    }
+
+    #[test]
+    fn picks_edges_near_the_point() {
+        use super::super::test_util::drawn;
+        use super::super::{world_index, GraphWorldIndex};
+
+        // 0 -> 1 straight across, from x = 50 to x = 200 along y = 25
+        let (mut world, _) = drawn(2, &[(0, 1)]);
+        world.insert(EdgeRouting::Straight);
+        world_index::set_node_pos(&mut world, NodeIndex::new(1), Pos { x: 200., y: 0. });
+        let edge = world.read_resource::<GraphWorldIndex>().edge_entity(EdgeIndex::new(0));
+        assert_eq!(edge_at(&world, 120., 27.), edge);
+        assert_eq!(edge_at(&world, 120., 40.), None);
+    }
+
+    #[test]
+    fn fresh_ids_skip_taken_ones() {
+        let mut draws = vec![0.5, 0.25, 0.].into_iter();
+        let half = (0.5 * ID_SPACE) as usize;
+        let id = fresh_id(|id| id == half, || draws.next().unwrap());
+        assert_eq!(id, (0.25 * ID_SPACE) as usize);
+    }
+}
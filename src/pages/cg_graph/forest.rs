@@ -0,0 +1,145 @@
+use super::{ConsensusEdge, ConsensusGoal};
+use petgraph::prelude::*;
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::{Bfs, VisitMap, Visitable};
+use std::collections::{HashMap, VecDeque};
+
+/// One weakly connected component of the goal graph.
+#[derive(Debug, Default)]
+pub struct Tree {
+    pub roots: Vec<NodeIndex>,
+    /// Every node of the component, breadth first from the roots.
+    pub order: Vec<NodeIndex>,
+}
+
+/// A goal is a root when it is marked as one. Components without a marked goal fall back to the
+/// goals nothing points at.
+pub fn trees(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<Tree> {
+    let mut uf = UnionFind::new(graph.node_count());
+    for edge in graph.raw_edges() {
+        uf.union(edge.source().index(), edge.target().index());
+    }
+
+    let mut by_label: HashMap<usize, usize> = HashMap::new();
+    let mut members: Vec<Vec<NodeIndex>> = vec![];
+    for idx in graph.node_indices() {
+        let slot = *by_label.entry(uf.find(idx.index())).or_insert_with(|| {
+            members.push(vec![]);
+            members.len() - 1
+        });
+        members[slot].push(idx);
+    }
+
+    let mut bfs = Bfs {
+        stack: VecDeque::new(),
+        discovered: graph.visit_map(),
+    };
+    members
+        .into_iter()
+        .map(|nodes| {
+            let mut roots: Vec<NodeIndex> = nodes.iter().copied().filter(|i| graph[*i].root).collect();
+            if roots.is_empty() {
+                roots = nodes
+                    .iter()
+                    .copied()
+                    .filter(|i| graph.neighbors_directed(*i, Incoming).next().is_none())
+                    .collect();
+            }
+            let mut order = Vec::with_capacity(nodes.len());
+            // roots first, then whatever they cannot reach
+            for start in roots.iter().chain(nodes.iter()) {
+                if !bfs.discovered.visit(*start) {
+                    continue;
+                }
+                bfs.stack.push_back(*start);
+                while let Some(idx) = bfs.next(graph) {
+                    order.push(idx);
+                }
+            }
+            Tree { roots, order }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+/// Splits a `width` x `height` canvas into a cell per tree, row major, with an area in proportion to
+/// how many goals `counts` says the tree has. Rows get taller and cells wider for bigger trees.
+pub fn regions(counts: &[usize], width: f64, height: f64) -> Vec<Region> {
+    if counts.is_empty() {
+        return vec![];
+    }
+    let cols = (counts.len() as f64).sqrt().ceil() as usize;
+    let counts: Vec<f64> = counts.iter().map(|c| (*c).max(1) as f64).collect();
+    let total: f64 = counts.iter().sum();
+    let mut cells = Vec::with_capacity(counts.len());
+    let mut y = 0.;
+    for row in counts.chunks(cols) {
+        let in_row: f64 = row.iter().sum();
+        let h = height * in_row / total;
+        let mut x = 0.;
+        for count in row {
+            let w = width * count / in_row;
+            cells.push(Region { x, y, w, h });
+            x += w;
+        }
+        y += h;
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::fixture;
+    use super::*;
+
+    fn idx(i: usize) -> NodeIndex {
+        NodeIndex::new(i)
+    }
+
+    #[test]
+    fn marked_roots_win_over_parentless_goals() {
+        // 0 -> 1 -> 2 with 1 marked
+        let mut graph = fixture(3, &[(0, 1), (1, 2)]);
+        graph[idx(1)].root = true;
+        let trees = trees(&graph);
+        assert_eq!(trees.len(), 1);
+        assert_eq!(trees[0].roots, vec![idx(1)]);
+        // what the root cannot reach still gets laid out, after the rest
+        assert_eq!(trees[0].order, vec![idx(1), idx(2), idx(0)]);
+    }
+
+    #[test]
+    fn unmarked_trees_start_from_parentless_goals() {
+        // 0 -> 2 <- 1, and 3 -> 4 on its own
+        let trees = trees(&fixture(5, &[(0, 2), (1, 2), (3, 4)]));
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[0].roots, vec![idx(0), idx(1)]);
+        assert_eq!(trees[1].roots, vec![idx(3)]);
+    }
+
+    #[test]
+    fn trees_without_sources_still_hold_every_goal() {
+        // 0 -> 1 -> 2 -> 0, feeding 3
+        let trees = trees(&fixture(4, &[(0, 1), (1, 2), (2, 0), (2, 3)]));
+        assert!(trees[0].roots.is_empty());
+        assert_eq!(trees[0].order, vec![idx(0), idx(1), idx(2), idx(3)]);
+    }
+
+    #[test]
+    fn regions_grow_with_their_trees() {
+        let cells = regions(&[30, 10, 5, 5], 900., 600.);
+        let area = |r: &Region| r.w * r.h;
+        assert_eq!(area(&cells[0]), 3. * area(&cells[1]));
+        assert_eq!(area(&cells[2]), area(&cells[3]));
+        // the cells tile the canvas
+        assert!((cells.iter().map(area).sum::<f64>() - 900. * 600.).abs() < 1e-6);
+        assert_eq!((cells[1].x + cells[1].w, cells[3].y + cells[3].h), (900., 600.));
+    }
+}
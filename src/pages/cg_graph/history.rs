@@ -0,0 +1,231 @@
+use super::patch::{edge_index, goal_index, GraphPatch};
+use super::world_index;
+use super::{ConsensusEdge, ConsensusGoal};
+use crate::components::Pos;
+use petgraph::prelude::*;
+use specs::World;
+use std::collections::VecDeque;
+
+const LIMIT: usize = 100;
+
+/// Graph changes plus where goals end up, keyed by goal id.
+#[derive(Debug, Default, Clone)]
+pub struct Step {
+    pub patches: Vec<GraphPatch>,
+    pub moves: Vec<(usize, Pos)>,
+}
+
+impl Step {
+    pub fn place(&self, id: usize) -> Option<Pos> {
+        self.moves.iter().find(|(goal, _)| *goal == id).map(|(_, pos)| *pos)
+    }
+}
+
+#[derive(Debug)]
+pub struct Command {
+    pub undo: Step,
+    pub redo: Step,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    done: VecDeque<Command>,
+    undone: Vec<Command>,
+    dragging: Vec<(usize, Pos)>,
+}
+
+impl History {
+    pub fn push(&mut self, cmd: Command) {
+        self.undone.clear();
+        if self.done.len() == LIMIT {
+            self.done.pop_front();
+        }
+        self.done.push_back(cmd);
+    }
+
+    pub fn undo(&mut self) -> Option<Step> {
+        let cmd = self.done.pop_back()?;
+        let step = cmd.undo.clone();
+        self.undone.push(cmd);
+        Some(step)
+    }
+
+    pub fn redo(&mut self) -> Option<Step> {
+        let cmd = self.undone.pop()?;
+        let step = cmd.redo.clone();
+        self.done.push_back(cmd);
+        Some(step)
+    }
+
+    /// Remembers where the goals were when the mouse went down.
+    pub fn begin_drag(&mut self, from: Vec<(usize, Pos)>) {
+        self.dragging = from;
+    }
+
+    /// Where `id` was when the drag in progress began, if the drag has it.
+    pub fn drag_start(&self, id: usize) -> Option<Pos> {
+        self.dragging.iter().find(|(held, _)| *held == id).map(|(_, from)| *from)
+    }
+
+    /// Adds a goal the drag moved without holding it, so undoing the drag puts it back too.
+    pub fn pushed(&mut self, id: usize, from: Pos) {
+        if !self.dragging.iter().any(|(held, _)| *held == id) {
+            self.dragging.push((id, from));
+        }
+    }
+
+    /// Turns everything moved since `begin_drag` into one command. Says whether anything moved.
+    pub fn end_drag(&mut self, world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> bool {
+        let mut cmd = Command {
+            undo: Step::default(),
+            redo: Step::default(),
+        };
+        for (id, from) in self.dragging.drain(..) {
+            let to = goal_index(graph, id).and_then(|idx| world_index::node_pos(world, idx));
+            match to {
+                Some(to) if to != from => {
+                    cmd.undo.moves.push((id, from));
+                    cmd.redo.moves.push((id, to));
+                }
+                _ => {}
+            }
+        }
+        let moved = !cmd.redo.moves.is_empty();
+        if moved {
+            self.push(cmd);
+        }
+        moved
+    }
+}
+
+/// The patches and placements that take the graph back to how it is now once `patch` is applied.
+pub fn inverse(world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, patch: &GraphPatch) -> Step {
+    use GraphPatch::*;
+    let mut step = Step::default();
+    match patch {
+        AddNode(goal) => step.patches.push(RemoveNode(goal.id)),
+        UpdateNode(goal) => {
+            if let Some(idx) = goal_index(graph, goal.id) {
+                step.patches.push(UpdateNode(graph[idx].clone()));
+            }
+        }
+        RemoveNode(id) => {
+            if let Some(idx) = goal_index(graph, *id) {
+                step.patches.push(AddNode(graph[idx].clone()));
+                for edge in graph.edges_directed(idx, Outgoing).chain(graph.edges_directed(idx, Incoming)) {
+                    step.patches.push(AddEdge(edge.weight().clone()));
+                }
+                if let Some(pos) = world_index::node_pos(world, idx) {
+                    step.moves.push((*id, pos));
+                }
+            }
+        }
+        AddEdge(edge) => step.patches.push(RemoveEdge(edge.id)),
+        UpdateEdge(edge) => {
+            if let Some(idx) = edge_index(graph, edge.id) {
+                step.patches.push(UpdateEdge(graph[idx].clone()));
+            }
+        }
+        RemoveEdge(id) => {
+            if let Some(idx) = edge_index(graph, *id) {
+                step.patches.push(AddEdge(graph[idx].clone()));
+            }
+        }
+    }
+    step
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::drawn;
+    use super::*;
+
+    fn at(x: f64) -> Pos {
+        Pos { x, y: 0. }
+    }
+
+    /// A command that moves goal `id`, so it can be told apart from the others.
+    fn command(id: usize) -> Command {
+        Command {
+            undo: Step { patches: vec![], moves: vec![(id, at(0.))] },
+            redo: Step { patches: vec![], moves: vec![(id, at(1.))] },
+        }
+    }
+
+    fn moved(step: Option<Step>) -> Option<(usize, Pos)> {
+        step.map(|step| step.moves[0])
+    }
+
+    #[test]
+    fn undo_and_redo_swap_commands_between_stacks() {
+        let mut history = History::default();
+        history.push(command(1));
+        history.push(command(2));
+        assert_eq!(moved(history.undo()), Some((2, at(0.))));
+        assert_eq!(moved(history.redo()), Some((2, at(1.))));
+        assert_eq!(moved(history.redo()), None);
+        assert_eq!(moved(history.undo()), Some((2, at(0.))));
+        assert_eq!(moved(history.undo()), Some((1, at(0.))));
+        assert_eq!(moved(history.undo()), None);
+    }
+
+    #[test]
+    fn new_commands_drop_what_was_undone() {
+        let mut history = History::default();
+        history.push(command(1));
+        history.undo();
+        history.push(command(2));
+        assert_eq!(moved(history.redo()), None);
+    }
+
+    #[test]
+    fn forgets_the_oldest_past_the_limit() {
+        let mut history = History::default();
+        for id in 0..=LIMIT {
+            history.push(command(id));
+        }
+        let undone: Vec<usize> = std::iter::from_fn(|| moved(history.undo())).map(|(id, _)| id).collect();
+        assert_eq!(undone, (1..=LIMIT).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn a_drag_is_one_command_for_everything_that_moved() {
+        let (mut world, graph) = drawn(3, &[]);
+        let mut history = History::default();
+        history.begin_drag(vec![(0, Pos::default()), (1, Pos::default())]);
+        // 0 held and moved, 1 held but left where it was, 2 pushed out of the way
+        history.pushed(2, Pos::default());
+        world_index::set_node_pos(&mut world, NodeIndex::new(0), at(5.));
+        world_index::set_node_pos(&mut world, NodeIndex::new(2), at(9.));
+        assert!(history.end_drag(&world, &graph));
+        let undo = history.undo().unwrap();
+        assert_eq!(undo.moves, vec![(0, Pos::default()), (2, Pos::default())]);
+        let redo = history.redo().unwrap();
+        assert_eq!(redo.moves, vec![(0, at(5.)), (2, at(9.))]);
+
+        // a click without a drag leaves nothing to undo
+        history.begin_drag(vec![(1, Pos::default())]);
+        assert!(!history.end_drag(&world, &graph));
+        assert_eq!(history.undo().map(|step| step.moves), Some(vec![(0, Pos::default()), (2, Pos::default())]));
+    }
+
+    #[test]
+    fn undoing_a_removal_brings_back_the_goal_its_edges_and_place() {
+        let (mut world, mut graph) = drawn(3, &[(0, 1), (1, 2)]);
+        world_index::set_node_pos(&mut world, NodeIndex::new(1), at(7.));
+        let removal = GraphPatch::RemoveNode(1);
+        let undo = inverse(&world, &graph, &removal);
+        removal.apply(&mut world, &mut graph, |_| Pos::default()).unwrap();
+        assert_eq!(graph.edge_count(), 0);
+
+        for patch in undo.patches.clone() {
+            patch.apply(&mut world, &mut graph, |_| undo.place(1).unwrap()).unwrap();
+        }
+        let idx = goal_index(&graph, 1).unwrap();
+        assert_eq!(graph[idx].st8mnt, "1");
+        assert_eq!(world_index::node_pos(&world, idx), Some(at(7.)));
+        let mut edges: Vec<usize> = graph.edge_indices().map(|e| graph[e].id).collect();
+        edges.sort();
+        assert_eq!(edges, vec![0, 1]);
+    }
+}
@@ -0,0 +1,205 @@
+use super::dot::{self, DotError};
+use super::GraphDocument;
+use std::fmt;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+
+pub use super::document::Placement;
+
+/// A graph together with its layout, as written to and read from files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GraphFile {
+    pub graph: GraphDocument,
+    pub positions: Vec<Placement>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Ron,
+    Bson,
+    Dot,
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    UnknownFormat(String),
+    Json(serde_json::Error),
+    Ron(ron::Error),
+    Bson(String),
+    Dot(DotError),
+    Browser(String),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use FileError::*;
+        match self {
+            UnknownFormat(name) => write!(f, "don't know how to read {}", name),
+            Json(e) => write!(f, "bad json: {}", e),
+            Ron(e) => write!(f, "bad ron: {}", e),
+            Bson(e) => write!(f, "bad bson: {}", e),
+            Dot(e) => write!(f, "bad dot: {}", e),
+            Browser(e) => write!(f, "browser refused: {}", e),
+        }
+    }
+}
+
+impl From<JsValue> for FileError {
+    fn from(err: JsValue) -> Self {
+        FileError::Browser(format!("{:?}", err))
+    }
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [Format::Json, Format::Ron, Format::Bson, Format::Dot];
+
+    pub fn from_file_name(name: &str) -> Result<Self, FileError> {
+        let ext = name.rsplit('.').next().unwrap_or_default().to_lowercase();
+        if ext == "gv" {
+            return Ok(Format::Dot);
+        }
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|f| f.extension() == ext)
+            .ok_or_else(|| FileError::UnknownFormat(name.to_string()))
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Ron => "ron",
+            Format::Bson => "bson",
+            Format::Dot => "dot",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Ron => "text/plain",
+            Format::Bson => "application/bson",
+            Format::Dot => "text/vnd.graphviz",
+        }
+    }
+
+    pub fn encode(self, file: &GraphFile) -> Result<Vec<u8>, FileError> {
+        match self {
+            Format::Json => serde_json::to_vec_pretty(file).map_err(FileError::Json),
+            Format::Ron => ron::ser::to_string_pretty(file, Default::default())
+                .map(String::into_bytes)
+                .map_err(FileError::Ron),
+            Format::Bson => {
+                let doc = match bson::to_bson(file).map_err(|e| FileError::Bson(e.to_string()))? {
+                    bson::Bson::Document(doc) => doc,
+                    other => return Err(FileError::Bson(format!("expected a document, got {}", other))),
+                };
+                let mut bytes = vec![];
+                doc.to_writer(&mut bytes).map_err(|e| FileError::Bson(e.to_string()))?;
+                Ok(bytes)
+            }
+            Format::Dot => Ok(dot::write(file).into_bytes()),
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<GraphFile, FileError> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(FileError::Json),
+            Format::Ron => ron::de::from_bytes(bytes).map_err(FileError::Ron),
+            Format::Bson => {
+                let doc = bson::Document::from_reader(&mut &bytes[..]).map_err(|e| FileError::Bson(e.to_string()))?;
+                bson::from_bson(bson::Bson::Document(doc)).map_err(|e| FileError::Bson(e.to_string()))
+            }
+            Format::Dot => {
+                let src = std::str::from_utf8(bytes).map_err(|e| FileError::Dot(DotError(e.to_string())))?;
+                dot::parse(src).map_err(FileError::Dot)
+            }
+        }
+    }
+}
+
+/// How long the browser gets to start a download before its object URL goes away.
+const REVOKE_AFTER_MS: i32 = 1000;
+
+/// Hands `bytes` to the browser as a file download.
+pub fn download(name: &str, mime: &str, bytes: &[u8]) -> Result<(), FileError> {
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let mut props = web_sys::BlobPropertyBag::new();
+    props.type_(mime);
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &props)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let anchor: web_sys::HtmlAnchorElement = seed::document().create_element("a")?.unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    // the download starts after this returns, revoking the URL now could cancel it
+    let revoke = Closure::once_into_js(move || {
+        let _ = web_sys::Url::revoke_object_url(&url);
+    });
+    seed::window().set_timeout_with_callback_and_timeout_and_arguments_0(revoke.unchecked_ref(), REVOKE_AFTER_MS)?;
+    Ok(())
+}
+
+/// Reads a file the user picked and decodes it by its extension.
+pub async fn upload(file: web_sys::File) -> Result<GraphFile, FileError> {
+    let format = Format::from_file_name(&file.name())?;
+    let buffer = wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await?;
+    format.decode(&js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pages::cg_graph::{ConsensusEdge, ConsensusGoal};
+
+    fn sample() -> GraphFile {
+        // ids past i32::MAX so bson has to store them as i64
+        let (a, b) = (3_000_000_000, 7);
+        let mut graph = GraphDocument::new(
+            vec![
+                ConsensusGoal { id: a, root: true, st8mnt: "say \"hi\"".to_string(), weight: 1.5, ..Default::default() },
+                ConsensusGoal { id: b, plugged: true, st8mnt: "two\nlines".to_string(), ..Default::default() },
+            ],
+            vec![ConsensusEdge { id: 4_000_000_000, label: "needs".to_string(), left: a, right: b, weight: 0.5 }],
+        );
+        graph.revision = 12;
+        GraphFile { graph, positions: vec![Placement { id: a, x: -10., y: 20.5 }] }
+    }
+
+    fn round_trip(format: Format) -> (GraphFile, GraphFile) {
+        let file = sample();
+        let back = format.decode(&format.encode(&file).unwrap()).unwrap();
+        (file, back)
+    }
+
+    fn same(a: &GraphFile, b: &GraphFile) {
+        assert_eq!(serde_json::to_value(a).unwrap(), serde_json::to_value(b).unwrap());
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let (file, back) = round_trip(Format::Json);
+        same(&file, &back);
+    }
+
+    #[test]
+    fn ron_round_trips() {
+        let (file, back) = round_trip(Format::Ron);
+        same(&file, &back);
+    }
+
+    #[test]
+    fn bson_round_trips_ids_past_i32() {
+        let (file, back) = round_trip(Format::Bson);
+        same(&file, &back);
+    }
+
+    #[test]
+    fn dot_round_trips_goals_edges_and_positions() {
+        // dot has no place for the revision
+        let (mut file, back) = round_trip(Format::Dot);
+        file.graph.revision = 0;
+        same(&file, &back);
+    }
+}
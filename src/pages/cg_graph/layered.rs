@@ -0,0 +1,143 @@
+use super::forest::{Region, Tree};
+use super::{ConsensusEdge, ConsensusGoal};
+use crate::components::Pos;
+use petgraph::prelude::*;
+use petgraph::visit::{DfsPostOrder, VisitMap};
+use std::collections::HashMap;
+
+/// Down-and-up barycenter passes to untangle each layer.
+const SWEEPS: usize = 4;
+
+/// Ranks the tree's goals so every edge points down, unless it closes a cycle. A goal sits one
+/// layer below the lowest goal it supports.
+fn ranks(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, tree: &Tree) -> HashMap<NodeIndex, usize> {
+    let mut post = Vec::with_capacity(tree.order.len());
+    let mut dfs = DfsPostOrder::empty(graph);
+    for start in tree.roots.iter().chain(&tree.order) {
+        if dfs.discovered.is_visited(start) {
+            continue;
+        }
+        dfs.move_to(*start);
+        while let Some(idx) = dfs.next(graph) {
+            post.push(idx);
+        }
+    }
+    // reverse post order puts every goal before its sub-goals, back edges aside
+    post.reverse();
+    let at: HashMap<NodeIndex, usize> = post.iter().enumerate().map(|(i, idx)| (*idx, i)).collect();
+    let mut rank: HashMap<NodeIndex, usize> = post.iter().map(|idx| (*idx, 0)).collect();
+    for idx in &post {
+        let below = rank[idx] + 1;
+        for child in graph.neighbors(*idx).filter(|child| at[child] > at[idx]) {
+            let r = rank.get_mut(&child).expect("child is in the same tree");
+            *r = (*r).max(below);
+        }
+    }
+    rank
+}
+
+/// Reorders `layer` by the mean slot of its neighbours in `fixed`. Goals without any keep their slot.
+fn barycenter(
+    graph: &DiGraph<ConsensusGoal, ConsensusEdge>,
+    layer: &mut Vec<NodeIndex>,
+    fixed: &[NodeIndex],
+    dir: Direction,
+) {
+    let slot: HashMap<NodeIndex, usize> = fixed.iter().enumerate().map(|(i, idx)| (*idx, i)).collect();
+    let mut keyed: Vec<(f64, NodeIndex)> = layer
+        .iter()
+        .enumerate()
+        .map(|(i, idx)| {
+            let near: Vec<usize> = graph.neighbors_directed(*idx, dir).filter_map(|n| slot.get(&n).copied()).collect();
+            if near.is_empty() {
+                (i as f64, *idx)
+            } else {
+                (near.iter().sum::<usize>() as f64 / near.len() as f64, *idx)
+            }
+        })
+        .collect();
+    keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    *layer = keyed.into_iter().map(|(_, idx)| idx).collect();
+}
+
+/// Lays one tree out in layers inside `region`, roots on top, `step` apart in both directions.
+/// Layers wider than the region overflow it to the right rather than overlap.
+pub fn layered(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, tree: &Tree, region: Region, step: f64) -> Vec<(NodeIndex, Pos)> {
+    let rank = ranks(graph, tree);
+    let depth = rank.values().copied().max().map_or(0, |d| d + 1);
+    let mut layers: Vec<Vec<NodeIndex>> = vec![vec![]; depth];
+    // breadth first order is a fair first guess
+    for idx in &tree.order {
+        layers[rank[idx]].push(*idx);
+    }
+
+    for _ in 0..SWEEPS {
+        for r in 1..layers.len() {
+            let (above, rest) = layers.split_at_mut(r);
+            barycenter(graph, &mut rest[0], &above[r - 1], Incoming);
+        }
+        for r in (0..layers.len().saturating_sub(1)).rev() {
+            let (upto, below) = layers.split_at_mut(r + 1);
+            barycenter(graph, &mut upto[r], &below[0], Outgoing);
+        }
+    }
+
+    let mut placed = Vec::with_capacity(tree.order.len());
+    for (r, layer) in layers.iter().enumerate() {
+        let span = (layer.len().max(1) - 1) as f64 * step;
+        let left = region.x + ((region.w - span) / 2.).max(0.);
+        for (i, idx) in layer.iter().enumerate() {
+            placed.push((*idx, Pos { x: left + i as f64 * step, y: region.y + r as f64 * step }));
+        }
+    }
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::forest;
+    use super::super::test_util::fixture;
+    use super::*;
+
+    const REGION: Region = Region { x: 0., y: 0., w: 900., h: 600. };
+
+    fn lay_out(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<(NodeIndex, Pos)> {
+        let trees = forest::trees(graph);
+        assert_eq!(trees.len(), 1);
+        layered(graph, &trees[0], REGION, 100.)
+    }
+
+    fn y_of(placed: &[(NodeIndex, Pos)], i: usize) -> f64 {
+        placed.iter().find(|(idx, _)| idx.index() == i).unwrap().1.y
+    }
+
+    #[test]
+    fn sub_goals_sit_below_every_goal_they_support() {
+        // 0 -> 1 -> 2 and a shortcut 0 -> 2
+        let graph = fixture(3, &[(0, 1), (1, 2), (0, 2)]);
+        let placed = lay_out(&graph);
+        assert_eq!((y_of(&placed, 0), y_of(&placed, 1), y_of(&placed, 2)), (0., 100., 200.));
+    }
+
+    #[test]
+    fn barycenters_uncross_a_layer() {
+        // 0 -> 3 and 1 -> 2, with 2 starting out left of 3
+        let graph = fixture(4, &[(0, 3), (1, 2)]);
+        let idx = NodeIndex::new;
+        let mut layer = vec![idx(2), idx(3)];
+        barycenter(&graph, &mut layer, &[idx(0), idx(1)], Incoming);
+        assert_eq!(layer, vec![idx(3), idx(2)]);
+    }
+
+    #[test]
+    fn goals_never_share_a_spot() {
+        let graph = fixture(7, &[(0, 1), (0, 2), (0, 3), (1, 4), (2, 5), (3, 6), (6, 0)]);
+        let placed = lay_out(&graph);
+        assert_eq!(placed.len(), 7);
+        for (i, (_, a)) in placed.iter().enumerate() {
+            for (_, b) in &placed[i + 1..] {
+                assert!(a.x != b.x || a.y != b.y);
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use super::forest::{self, Region, Tree};
+use super::{ecs, layered, radial};
+use super::{ConsensusEdge, ConsensusGoal, GraphWorldIndex};
+use crate::components::{Dimension, Edge, Hidden, Interactable, Pinned, Pos};
+use petgraph::prelude::*;
+use specs::prelude::*;
+use std::fmt;
+
+/// Decides where every goal goes. Positions are indexed like the graph's nodes, and `sizes` gives
+/// each goal's size the same way. `area` is the canvas to fill.
+pub trait LayoutStrategy {
+    fn name(&self) -> &'static str;
+    fn place(&self, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, sizes: &[Dimension], area: (f64, f64)) -> Vec<Pos>;
+}
+
+impl fmt::Debug for dyn LayoutStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub const ALL: [&dyn LayoutStrategy; 4] = [&Grid, &Layered, &Radial, &Force];
+
+pub fn by_name(name: &str) -> Option<&'static dyn LayoutStrategy> {
+    ALL.iter().copied().find(|l| l.name() == name)
+}
+
+/// The biggest goal, and how far apart goals go so that it fits with room to spare.
+fn spacing(sizes: &[Dimension]) -> (f64, f64) {
+    let size = sizes.iter().fold(0f64, |m, d| m.max(d.w).max(d.h));
+    (size, size * 2.)
+}
+
+/// Gives each weakly connected component its own patch of `area` and lays them out one by one.
+fn per_tree(
+    graph: &DiGraph<ConsensusGoal, ConsensusEdge>,
+    (width, height): (f64, f64),
+    mut lay_out: impl FnMut(&Tree, Region) -> Vec<(NodeIndex, Pos)>,
+) -> Vec<Pos> {
+    let trees = forest::trees(graph);
+    let counts: Vec<usize> = trees.iter().map(|tree| tree.order.len()).collect();
+    let mut placed = vec![Pos::default(); graph.node_count()];
+    for (tree, region) in trees.iter().zip(forest::regions(&counts, width, height)) {
+        for (idx, pos) in lay_out(tree, region) {
+            placed[idx.index()] = pos;
+        }
+    }
+    placed
+}
+
+/// Rows in breadth first order.
+pub struct Grid;
+
+impl LayoutStrategy for Grid {
+    fn name(&self) -> &'static str {
+        "grid"
+    }
+
+    fn place(&self, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, sizes: &[Dimension], area: (f64, f64)) -> Vec<Pos> {
+        let (_, step) = spacing(sizes);
+        per_tree(graph, area, |tree, region| {
+            let row_count = ((region.w / step) as usize).max(1);
+            tree.order
+                .iter()
+                .enumerate()
+                .map(|(i, idx)| {
+                    let x = region.x + (i % row_count) as f64 * step;
+                    let y = region.y + (i / row_count) as f64 * step;
+                    (*idx, Pos { x, y })
+                })
+                .collect()
+        })
+    }
+}
+
+pub struct Layered;
+
+impl LayoutStrategy for Layered {
+    fn name(&self) -> &'static str {
+        "layered"
+    }
+
+    fn place(&self, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, sizes: &[Dimension], area: (f64, f64)) -> Vec<Pos> {
+        let (_, step) = spacing(sizes);
+        per_tree(graph, area, |tree, region| layered::layered(graph, tree, region, step))
+    }
+}
+
+pub struct Radial;
+
+impl LayoutStrategy for Radial {
+    fn name(&self) -> &'static str {
+        "radial"
+    }
+
+    fn place(&self, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, sizes: &[Dimension], area: (f64, f64)) -> Vec<Pos> {
+        let (size, step) = spacing(sizes);
+        per_tree(graph, area, |tree, region| radial::radial(graph, tree, region, step, size))
+    }
+}
+
+/// Runs the force layout to rest off screen, starting from the layered one.
+pub struct Force;
+
+impl Force {
+    /// Simulated frames before we give up on it settling.
+    const FRAMES: usize = 600;
+    /// Pairs of goals pushed apart over all frames. Every frame pushes every pair, so big graphs
+    /// get fewer frames rather than freezing the page.
+    const PAIRS: usize = 4_000_000;
+}
+
+impl LayoutStrategy for Force {
+    fn name(&self) -> &'static str {
+        "force"
+    }
+
+    fn place(&self, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, sizes: &[Dimension], area: (f64, f64)) -> Vec<Pos> {
+        let mut world = World::new();
+        world.register::<Pos>();
+        world.register::<Dimension>();
+        world.register::<Edge>();
+        world.register::<Interactable>();
+        world.register::<Pinned>();
+        world.register::<Hidden>();
+        ecs::register(&mut world);
+
+        let start = Layered.place(graph, sizes, area);
+        let goals: Vec<Entity> = start
+            .iter()
+            .zip(sizes)
+            .map(|(pos, dim)| world.create_entity().with(*pos).with(*dim).build())
+            .collect();
+        for edge in graph.raw_edges() {
+            let link = Edge { left: goals[edge.source().index()], right: goals[edge.target().index()] };
+            world.create_entity().with(link).build();
+        }
+        let frames = (Self::PAIRS / goals.len().pow(2).max(1)).min(Self::FRAMES);
+        for _ in 0..frames {
+            if !ecs::step(&mut world, 1. / 60.) {
+                break;
+            }
+        }
+        let poss = world.read_storage::<Pos>();
+        goals.iter().map(|ent| *poss.get(*ent).expect("goal keeps its Pos")).collect()
+    }
+}
+
+/// Moves goals from where they are to where a layout put them, easing in and out. Goals are held
+/// by entity, so edits in between cannot send a move to the wrong goal.
+#[derive(Debug)]
+pub struct Transition {
+    moves: Vec<(Entity, Pos, Pos)>,
+    elapsed: f64,
+}
+
+impl Transition {
+    pub const SECONDS: f64 = 0.6;
+
+    pub fn new(moves: Vec<(Entity, Pos, Pos)>) -> Self {
+        Self { moves, elapsed: 0. }
+    }
+
+    /// From where goals are to where `placed` has them, indexed like `graph`'s nodes. Pinned goals
+    /// stay where they are.
+    pub fn to(world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, placed: &[Pos]) -> Self {
+        let (index, poss, pinned) = (
+            world.read_resource::<GraphWorldIndex>(),
+            world.read_storage::<Pos>(),
+            world.read_storage::<Pinned>(),
+        );
+        let moves = graph
+            .node_indices()
+            .filter_map(|idx| {
+                let ent = index.node_entity(idx).filter(|ent| !pinned.contains(*ent))?;
+                Some((ent, *poss.get(ent)?, placed[idx.index()]))
+            })
+            .collect();
+        Self::new(moves)
+    }
+
+    /// Where everything is `seconds` later, and whether it has arrived.
+    pub fn advance(&mut self, seconds: f64) -> (Vec<(Entity, Pos)>, bool) {
+        self.elapsed = (self.elapsed + seconds).min(Self::SECONDS);
+        let t = self.elapsed / Self::SECONDS;
+        let eased = t * t * (3. - 2. * t);
+        let at = self
+            .moves
+            .iter()
+            .map(|(ent, from, to)| {
+                (*ent, Pos { x: from.x + (to.x - from.x) * eased, y: from.y + (to.y - from.y) * eased })
+            })
+            .collect();
+        (at, t >= 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::{drawn, fixture};
+    use super::*;
+
+    const AREA: (f64, f64) = (900., 600.);
+
+    fn sizes(count: usize) -> Vec<Dimension> {
+        (0..count).map(|_| Dimension { w: 50., h: 50. }).collect()
+    }
+
+    fn distance(a: Pos, b: Pos) -> f64 {
+        ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn every_strategy_keeps_goals_apart() {
+        let graph = fixture(7, &[(0, 1), (0, 2), (1, 3), (1, 4), (2, 5), (2, 6)]);
+        for layout in ALL.iter() {
+            let placed = layout.place(&graph, &sizes(7), AREA);
+            assert_eq!(placed.len(), 7, "{}", layout.name());
+            for (i, a) in placed.iter().enumerate() {
+                for b in &placed[i + 1..] {
+                    assert!(distance(*a, *b) >= 50., "{} put goals on top of each other", layout.name());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strategies_are_found_by_name() {
+        for layout in ALL.iter() {
+            assert_eq!(by_name(layout.name()).map(|l| l.name()), Some(layout.name()));
+        }
+        assert!(by_name("spiral").is_none());
+    }
+
+    #[test]
+    fn grid_fills_rows() {
+        let graph = fixture(3, &[(0, 1), (1, 2)]);
+        let placed = Grid.place(&graph, &sizes(3), AREA);
+        assert!(placed.iter().all(|pos| pos.y == 0.));
+        assert_eq!(placed.iter().map(|pos| pos.x).collect::<Vec<_>>(), vec![0., 100., 200.]);
+    }
+
+    #[test]
+    fn force_pulls_linked_goals_together() {
+        // 0 - 1 linked, 2 on its own
+        let graph = fixture(3, &[(0, 1)]);
+        let placed = Force.place(&graph, &sizes(3), AREA);
+        assert!(distance(placed[0], placed[1]) < distance(placed[0], placed[2]));
+    }
+
+    #[test]
+    fn transitions_leave_pinned_goals_alone() {
+        let (mut world, graph) = drawn(2, &[(0, 1)]);
+        world.register::<Pinned>();
+        let pinned = world.read_resource::<GraphWorldIndex>().node_entity(NodeIndex::new(0)).unwrap();
+        world.write_storage::<Pinned>().insert(pinned, Pinned).unwrap();
+        let mut tween = Transition::to(&world, &graph, &[Pos { x: 100., y: 0. }, Pos { x: 0., y: 100. }]);
+        let (at, _) = tween.advance(Transition::SECONDS);
+        let free = world.read_resource::<GraphWorldIndex>().node_entity(NodeIndex::new(1)).unwrap();
+        assert_eq!(at, vec![(free, Pos { x: 0., y: 100. })]);
+    }
+
+    #[test]
+    fn transitions_ease_and_arrive() {
+        let ent = World::new().create_entity().build();
+        let mut tween = Transition::new(vec![(ent, Pos { x: 0., y: 0. }, Pos { x: 100., y: 10. })]);
+        let (at, done) = tween.advance(Transition::SECONDS / 2.);
+        assert!(!done);
+        assert_eq!(at, vec![(ent, Pos { x: 50., y: 5. })]);
+        let (at, done) = tween.advance(Transition::SECONDS);
+        assert!(done);
+        assert_eq!(at, vec![(ent, Pos { x: 100., y: 10. })]);
+    }
+}
@@ -0,0 +1,168 @@
+use super::document::GraphError;
+use super::world_index;
+use super::{ConsensusEdge, ConsensusGoal};
+use crate::components::Pos;
+use petgraph::prelude::*;
+use specs::World;
+
+/// A single change to the graph, as exchanged with `api/graph/cg_graph/patches`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", content = "data")]
+pub enum GraphPatch {
+    AddNode(ConsensusGoal),
+    UpdateNode(ConsensusGoal),
+    RemoveNode(usize),
+    AddEdge(ConsensusEdge),
+    UpdateEdge(ConsensusEdge),
+    RemoveEdge(usize),
+}
+
+/// Everything that happened between revision `since` and `revision`.
+#[derive(Debug, Deserialize)]
+pub struct PatchSet {
+    pub since: u64,
+    pub revision: u64,
+    pub patches: Vec<GraphPatch>,
+}
+
+pub fn goal_index(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, id: usize) -> Option<NodeIndex> {
+    graph.node_indices().find(|i| graph[*i].id == id)
+}
+
+pub fn edge_index(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, id: usize) -> Option<EdgeIndex> {
+    graph.edge_indices().find(|i| graph[*i].id == id)
+}
+
+fn endpoints(
+    graph: &DiGraph<ConsensusGoal, ConsensusEdge>,
+    edge: &ConsensusEdge,
+) -> Result<(NodeIndex, NodeIndex), GraphError> {
+    if edge.left == edge.right {
+        return Err(GraphError::SelfLoop { edge: edge.id, goal: edge.left });
+    }
+    let left = goal_index(graph, edge.left).ok_or(GraphError::DanglingEdge { edge: edge.id, goal: edge.left })?;
+    let right = goal_index(graph, edge.right).ok_or(GraphError::DanglingEdge { edge: edge.id, goal: edge.right })?;
+    Ok((left, right))
+}
+
+impl GraphPatch {
+    /// Applies the change to the graph and the entities drawing it. New goals are put at
+    /// `place(n)`, where `n` is the number of goals before they were added.
+    pub fn apply(
+        self,
+        world: &mut World,
+        graph: &mut DiGraph<ConsensusGoal, ConsensusEdge>,
+        place: impl FnOnce(usize) -> Pos,
+    ) -> Result<(), GraphError> {
+        use GraphPatch::*;
+        match self {
+            AddNode(goal) => {
+                if goal_index(graph, goal.id).is_some() {
+                    return Err(GraphError::DuplicateGoal(goal.id));
+                }
+                let pos = place(graph.node_count());
+                world_index::add_node(world, graph, goal, pos);
+            }
+            UpdateNode(goal) => {
+                let idx = goal_index(graph, goal.id).ok_or(GraphError::UnknownGoal(goal.id))?;
+                world_index::update_node(world, graph, idx, goal);
+            }
+            RemoveNode(id) => {
+                let idx = goal_index(graph, id).ok_or(GraphError::UnknownGoal(id))?;
+                world_index::remove_node(world, graph, idx);
+            }
+            AddEdge(edge) => {
+                if edge_index(graph, edge.id).is_some() {
+                    return Err(GraphError::DuplicateEdge(edge.id));
+                }
+                let (left, right) = endpoints(graph, &edge)?;
+                world_index::add_edge(world, graph, left, right, edge);
+            }
+            UpdateEdge(edge) => {
+                let idx = edge_index(graph, edge.id).ok_or(GraphError::UnknownEdge(edge.id))?;
+                let (left, right) = endpoints(graph, &edge)?;
+                if graph.edge_endpoints(idx) == Some((left, right)) {
+                    graph[idx] = edge;
+                } else {
+                    world_index::remove_edge(world, graph, idx);
+                    world_index::add_edge(world, graph, left, right, edge);
+                }
+            }
+            RemoveEdge(id) => {
+                let idx = edge_index(graph, id).ok_or(GraphError::UnknownEdge(id))?;
+                world_index::remove_edge(world, graph, idx);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::drawn;
+    use super::super::GraphWorldIndex;
+    use super::*;
+    use crate::components::{Edge, Text};
+    use specs::prelude::*;
+
+    /// Every goal and edge maps to a live entity and back, and edges link their goals' entities.
+    fn assert_indexed(world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>) {
+        let index = world.read_resource::<GraphWorldIndex>();
+        let (texts, links) = (world.read_storage::<Text>(), world.read_storage::<Edge>());
+        assert_eq!(index.nodes().count(), graph.node_count());
+        assert_eq!(index.edges().count(), graph.edge_count());
+        for idx in graph.node_indices() {
+            let ent = index.node_entity(idx).unwrap();
+            assert!(world.is_alive(ent));
+            assert_eq!(index.entity_node(ent), Some(idx));
+            assert_eq!(texts.get(ent).unwrap().st, graph[idx].st8mnt);
+        }
+        for idx in graph.edge_indices() {
+            let ent = index.edge_entity(idx).unwrap();
+            assert!(world.is_alive(ent));
+            assert_eq!(index.entity_edge(ent), Some(idx));
+            let (left, right) = graph.edge_endpoints(idx).unwrap();
+            let link = links.get(ent).unwrap();
+            assert_eq!((link.left, link.right), (index.node_entity(left).unwrap(), index.node_entity(right).unwrap()));
+        }
+    }
+
+    #[test]
+    fn removing_a_middle_goal_keeps_the_index_whole() {
+        let (mut world, mut graph) = drawn(4, &[(0, 1), (1, 2), (2, 3), (3, 0)]);
+        GraphPatch::RemoveNode(1).apply(&mut world, &mut graph, |_| Pos::default()).unwrap();
+        assert_eq!((graph.node_count(), graph.edge_count()), (3, 2));
+        assert!(goal_index(&graph, 1).is_none());
+        assert_indexed(&world, &graph);
+    }
+
+    #[test]
+    fn removing_a_middle_edge_keeps_the_index_whole() {
+        let (mut world, mut graph) = drawn(4, &[(0, 1), (1, 2), (2, 3)]);
+        GraphPatch::RemoveEdge(1).apply(&mut world, &mut graph, |_| Pos::default()).unwrap();
+        assert_eq!(graph.edge_count(), 2);
+        assert!(edge_index(&graph, 1).is_none());
+        assert_indexed(&world, &graph);
+    }
+
+    #[test]
+    fn moving_an_edge_keeps_the_index_whole() {
+        let (mut world, mut graph) = drawn(3, &[(0, 1), (1, 2)]);
+        let moved = ConsensusEdge { id: 0, left: 2, right: 0, ..Default::default() };
+        GraphPatch::UpdateEdge(moved).apply(&mut world, &mut graph, |_| Pos::default()).unwrap();
+        let idx = edge_index(&graph, 0).unwrap();
+        assert_eq!(graph.edge_endpoints(idx), Some((NodeIndex::new(2), NodeIndex::new(0))));
+        assert_indexed(&world, &graph);
+    }
+
+    #[test]
+    fn refuses_patches_that_do_not_fit() {
+        let (mut world, mut graph) = drawn(2, &[(0, 1)]);
+        let mut apply = |patch: GraphPatch| patch.apply(&mut world, &mut graph, |_| Pos::default());
+        let twin = ConsensusGoal { id: 1, ..Default::default() };
+        assert_eq!(apply(GraphPatch::AddNode(twin)), Err(GraphError::DuplicateGoal(1)));
+        assert_eq!(apply(GraphPatch::RemoveNode(9)), Err(GraphError::UnknownGoal(9)));
+        let dangling = ConsensusEdge { id: 5, left: 0, right: 9, ..Default::default() };
+        assert_eq!(apply(GraphPatch::AddEdge(dangling)), Err(GraphError::DanglingEdge { edge: 5, goal: 9 }));
+    }
+}
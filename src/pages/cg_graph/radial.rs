@@ -0,0 +1,144 @@
+use super::forest::{Region, Tree};
+use super::{ConsensusEdge, ConsensusGoal};
+use crate::components::Pos;
+use petgraph::prelude::*;
+use petgraph::visit::{Bfs, VisitMap, Visitable};
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::PI;
+
+/// Puts the tree's root in the middle of `region` and every breadth first level on a ring around
+/// it, `step` apart or closer if the rings would not fit. Each goal gets a wedge of its parent's
+/// wedge as large as its share of the parent's subtree. Goals are `size` wide; positions are their
+/// top left corners, like `Pos` everywhere else.
+pub fn radial(
+    graph: &DiGraph<ConsensusGoal, ConsensusEdge>,
+    tree: &Tree,
+    region: Region,
+    step: f64,
+    size: f64,
+) -> Vec<(NodeIndex, Pos)> {
+    // breadth first from the roots, then from whatever they cannot reach
+    let mut bfs = Bfs { stack: VecDeque::new(), discovered: graph.visit_map() };
+    let mut order: Vec<NodeIndex> = Vec::with_capacity(tree.order.len());
+    let mut tops: Vec<NodeIndex> = vec![];
+    for start in tree.roots.iter().chain(&tree.order) {
+        if !bfs.discovered.visit(*start) {
+            continue;
+        }
+        tops.push(*start);
+        bfs.stack.push_back(*start);
+        while let Some(idx) = bfs.next(graph) {
+            order.push(idx);
+        }
+    }
+
+    // a goal hangs off whichever goal found it first, which is its earliest parent in `order`
+    let at: HashMap<NodeIndex, usize> = order.iter().enumerate().map(|(i, idx)| (*idx, i)).collect();
+    let mut children: HashMap<NodeIndex, Vec<NodeIndex>> = HashMap::new();
+    for idx in order.iter().filter(|idx| !tops.contains(idx)) {
+        let parent = graph
+            .neighbors_directed(*idx, Incoming)
+            .filter(|p| at.get(p).map_or(false, |i| *i < at[idx]))
+            .min_by_key(|p| at[p])
+            .expect("only starts have no earlier parent");
+        children.entry(parent).or_default().push(*idx);
+    }
+    for kids in children.values_mut() {
+        kids.sort_by_key(|k| at[k]);
+    }
+
+    let mut weight: HashMap<NodeIndex, usize> = HashMap::new();
+    for idx in order.iter().rev() {
+        let below: usize = children.get(idx).map_or(0, |kids| kids.iter().map(|k| weight[k]).sum());
+        weight.insert(*idx, below + 1);
+    }
+
+    // one top sits in the middle; several share the first ring around an empty middle
+    let mut ring: HashMap<NodeIndex, usize> = HashMap::new();
+    let mut wedge: HashMap<NodeIndex, (f64, f64)> = HashMap::new();
+    let split = |parts: &[NodeIndex], (from, to): (f64, f64), wedge: &mut HashMap<NodeIndex, (f64, f64)>| {
+        let total: usize = parts.iter().map(|p| weight[p]).sum();
+        let mut start = from;
+        for part in parts {
+            let end = start + (to - from) * weight[part] as f64 / total as f64;
+            wedge.insert(*part, (start, end));
+            start = end;
+        }
+    };
+    let first = if tops.len() == 1 { 0 } else { 1 };
+    split(&tops, (0., 2. * PI), &mut wedge);
+    for top in &tops {
+        ring.insert(*top, first);
+    }
+    for idx in &order {
+        if let Some(kids) = children.get(idx) {
+            split(kids, wedge[idx], &mut wedge);
+            for kid in kids {
+                ring.insert(*kid, ring[idx] + 1);
+            }
+        }
+    }
+
+    let rings = ring.values().copied().max().unwrap_or(0);
+    let (cx, cy) = (region.x + region.w / 2., region.y + region.h / 2.);
+    let fits = (region.w.min(region.h) - size) / 2.;
+    let spacing = if rings == 0 { 0. } else { step.min(fits / rings as f64).max(size) };
+    order
+        .iter()
+        .map(|idx| {
+            let (from, to) = wedge[idx];
+            let (angle, r) = ((from + to) / 2., ring[idx] as f64 * spacing);
+            (*idx, Pos { x: cx + r * angle.cos() - size / 2., y: cy + r * angle.sin() - size / 2. })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::forest;
+    use super::super::test_util::fixture;
+    use super::*;
+
+    const REGION: Region = Region { x: 0., y: 0., w: 1000., h: 1000. };
+
+    /// Distance of each goal's centre from the middle of the region, by node index.
+    fn radii(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<f64> {
+        let trees = forest::trees(graph);
+        let mut placed = radial(graph, &trees[0], REGION, 100., 50.);
+        placed.sort_by_key(|(idx, _)| idx.index());
+        placed
+            .iter()
+            .map(|(_, pos)| ((pos.x + 25. - 500.).powi(2) + (pos.y + 25. - 500.).powi(2)).sqrt().round())
+            .collect()
+    }
+
+    #[test]
+    fn levels_go_on_rings_round_the_root() {
+        let graph = fixture(5, &[(0, 1), (0, 2), (1, 3), (2, 4)]);
+        assert_eq!(radii(&graph), vec![0., 100., 100., 200., 200.]);
+    }
+
+    #[test]
+    fn bigger_subtrees_get_wider_wedges() {
+        // 1 carries three goals, 2 none
+        let graph = fixture(6, &[(0, 1), (0, 2), (1, 3), (1, 4), (1, 5)]);
+        let trees = forest::trees(&graph);
+        let placed: HashMap<NodeIndex, Pos> = radial(&graph, &trees[0], REGION, 100., 50.).into_iter().collect();
+        let angle = |i: usize| {
+            let pos = placed[&NodeIndex::new(i)];
+            (pos.y + 25. - 500.).atan2(pos.x + 25. - 500.).rem_euclid(2. * PI)
+        };
+        // 1 owns 4/5 of the circle and splits it evenly between its three sub-goals
+        let mut under: Vec<f64> = (3..6).map(angle).collect();
+        under.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for pair in under.windows(2) {
+            assert!((pair[1] - pair[0] - 2. * PI * 4. / 5. / 3.).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn shrinks_rings_to_fit() {
+        let graph = fixture(8, &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6), (6, 7)]);
+        assert!(radii(&graph).iter().all(|r| *r <= 475.));
+    }
+}
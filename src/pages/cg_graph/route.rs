@@ -0,0 +1,395 @@
+use crate::components::{Dimension, Pos};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// How far apart parallel edges run, in px.
+const LANE: f64 = 12.;
+/// How far a curved edge bows out when nothing is in its way.
+const BOW: f64 = 30.;
+/// How much further each retry moves a path to get round a goal.
+const DETOUR: f64 = 40.;
+const TRIES: usize = 4;
+/// How far an orthogonal edge runs straight out of a goal before it turns.
+const STUB: f64 = 15.;
+const ARROW_LENGTH: f64 = 10.;
+const ARROW_WIDTH: f64 = 5.;
+
+pub type Point = (f64, f64);
+
+/// How edges get from one goal to the other. A world resource, read by the renderers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeRouting {
+    Straight,
+    /// Bends round goals in the way.
+    Curved,
+    /// Only horizontal and vertical runs, round goals in the way.
+    Orthogonal,
+}
+
+impl Default for EdgeRouting {
+    fn default() -> Self {
+        EdgeRouting::Straight
+    }
+}
+
+impl EdgeRouting {
+    pub const ALL: [EdgeRouting; 3] = [EdgeRouting::Straight, EdgeRouting::Curved, EdgeRouting::Orthogonal];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EdgeRouting::Straight => "straight",
+            EdgeRouting::Curved => "curved",
+            EdgeRouting::Orthogonal => "orthogonal",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|r| r.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+impl Rect {
+    pub fn of(pos: &Pos, dim: &Dimension) -> Self {
+        Rect { x: pos.x, y: pos.y, w: dim.w, h: dim.h }
+    }
+
+    fn centre(&self) -> Point {
+        (self.x + self.w / 2., self.y + self.h / 2.)
+    }
+
+    fn contains(&self, (x, y): Point) -> bool {
+        x > self.x && x < self.x + self.w && y > self.y && y < self.y + self.h
+    }
+
+    /// Where a ray from the middle towards `to` leaves the rectangle.
+    fn clip(&self, to: Point) -> Point {
+        let (cx, cy) = self.centre();
+        let (dx, dy) = (to.0 - cx, to.1 - cy);
+        if dx == 0. && dy == 0. {
+            return (cx, cy);
+        }
+        let tx = if dx == 0. { f64::INFINITY } else { self.w / 2. / dx.abs() };
+        let ty = if dy == 0. { f64::INFINITY } else { self.h / 2. / dy.abs() };
+        let t = tx.min(ty);
+        (cx + dx * t, cy + dy * t)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EdgePath {
+    Polyline(Vec<Point>),
+    /// A quadratic curve.
+    Curve { from: Point, ctrl: Point, to: Point },
+}
+
+impl EdgePath {
+    fn points(&self) -> Vec<Point> {
+        match self {
+            EdgePath::Polyline(points) => points.clone(),
+            EdgePath::Curve { from, ctrl, to } => (0..=16)
+                .map(|i| {
+                    let t = i as f64 / 16.;
+                    let u = 1. - t;
+                    (
+                        u * u * from.0 + 2. * u * t * ctrl.0 + t * t * to.0,
+                        u * u * from.1 + 2. * u * t * ctrl.1 + t * t * to.1,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether the path runs through any of `rects` other than its own `ends`. Only rects
+    /// overlapping the path's bounds get sampled.
+    fn crosses(&self, rects: &[Rect], ends: [Rect; 2]) -> bool {
+        let points = self.points();
+        let (mut lo, mut hi) = (points[0], points[0]);
+        for (x, y) in &points {
+            lo = (lo.0.min(*x), lo.1.min(*y));
+            hi = (hi.0.max(*x), hi.1.max(*y));
+        }
+        let near: Vec<&Rect> = rects
+            .iter()
+            .filter(|r| r.x < hi.0 && r.x + r.w > lo.0 && r.y < hi.1 && r.y + r.h > lo.1 && !ends.contains(r))
+            .collect();
+        if near.is_empty() {
+            return false;
+        }
+        points.windows(2).any(|seg| {
+            (0..=8).any(|i| {
+                let t = i as f64 / 8.;
+                let p = (seg[0].0 + (seg[1].0 - seg[0].0) * t, seg[0].1 + (seg[1].1 - seg[0].1) * t);
+                near.iter().any(|r| r.contains(p))
+            })
+        })
+    }
+
+    /// Whether the path comes within `reach` of `p`.
+    pub fn passes_near(&self, p: Point, reach: f64) -> bool {
+        self.points().windows(2).any(|seg| {
+            let (dx, dy) = (seg[1].0 - seg[0].0, seg[1].1 - seg[0].1);
+            let len = dx * dx + dy * dy;
+            let t = if len == 0. { 0. } else { (((p.0 - seg[0].0) * dx + (p.1 - seg[0].1) * dy) / len).max(0.).min(1.) };
+            let (cx, cy) = (seg[0].0 + dx * t, seg[0].1 + dy * t);
+            (p.0 - cx).powi(2) + (p.1 - cy).powi(2) <= reach * reach
+        })
+    }
+
+    /// The point the path arrives at, and where it comes in from.
+    fn last_leg(&self) -> (Point, Point) {
+        match self {
+            EdgePath::Polyline(points) => (points[points.len() - 2], points[points.len() - 1]),
+            EdgePath::Curve { ctrl, to, .. } => (*ctrl, *to),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routed {
+    pub path: EdgePath,
+    /// Tip first. Missing when the ends sit on top of each other.
+    pub arrow: Option<[Point; 3]>,
+}
+
+fn arrow((from, tip): (Point, Point)) -> Option<[Point; 3]> {
+    let (dx, dy) = (tip.0 - from.0, tip.1 - from.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == 0. {
+        return None;
+    }
+    let (ux, uy) = (dx / len, dy / len);
+    let (bx, by) = (tip.0 - ux * ARROW_LENGTH, tip.1 - uy * ARROW_LENGTH);
+    Some([tip, (bx - uy * ARROW_WIDTH, by + ux * ARROW_WIDTH), (bx + uy * ARROW_WIDTH, by - ux * ARROW_WIDTH)])
+}
+
+/// Sideways offset for each edge so edges between the same two goals do not overlap, whichever
+/// way they point. Offsets are signed against the edge's own direction.
+pub fn lanes<T: Copy + Ord + Hash>(ends: &[(T, T)]) -> Vec<f64> {
+    let key = |(a, b): (T, T)| if a <= b { (a, b) } else { (b, a) };
+    let mut count: HashMap<(T, T), usize> = HashMap::new();
+    for end in ends {
+        *count.entry(key(*end)).or_default() += 1;
+    }
+    let mut seen: HashMap<(T, T), usize> = HashMap::new();
+    ends.iter()
+        .map(|end| {
+            let k = key(*end);
+            let i = seen.entry(k).or_default();
+            let lane = (*i as f64 - (count[&k] - 1) as f64 / 2.) * LANE;
+            *i += 1;
+            if end.0 > end.1 { -lane } else { lane }
+        })
+        .collect()
+}
+
+/// A curve from `from` to `to` bowed `bend` px to the left of the straight line, clipped to both.
+fn bowed(from: Rect, to: Rect, bend: f64) -> EdgePath {
+    let ((fx, fy), (tx, ty)) = (from.centre(), to.centre());
+    let (dx, dy) = (tx - fx, ty - fy);
+    let len = (dx * dx + dy * dy).sqrt().max(1.);
+    // the control point sits twice as far out as the curve's middle
+    let ctrl = ((fx + tx) / 2. - dy / len * bend * 2., (fy + ty) / 2. + dx / len * bend * 2.);
+    EdgePath::Curve { from: from.clip(ctrl), ctrl, to: to.clip(ctrl) }
+}
+
+/// Straight out of the side of `from` facing `to`, across to a middle run, along it, and across
+/// into the near side of `to`. `shift` moves the middle run off the halfway line.
+fn elbow(from: Rect, to: Rect, vertical: bool, shift: f64) -> EdgePath {
+    let ((fx, fy), (tx, ty)) = (from.centre(), to.centre());
+    let points = if vertical {
+        let (down, mid) = ((ty - fy).signum(), (fx + tx) / 2. + shift);
+        let (out, into) = (fy + down * (from.h / 2. + STUB), ty - down * (to.h / 2. + STUB));
+        vec![(fx, fy + down * from.h / 2.), (fx, out), (mid, out), (mid, into), (tx, into), (tx, ty - down * to.h / 2.)]
+    } else {
+        let (right, mid) = ((tx - fx).signum(), (fy + ty) / 2. + shift);
+        let (out, into) = (fx + right * (from.w / 2. + STUB), tx - right * (to.w / 2. + STUB));
+        vec![(fx + right * from.w / 2., fy), (out, fy), (out, mid), (into, mid), (into, ty), (tx - right * to.w / 2., ty)]
+    };
+    EdgePath::Polyline(points)
+}
+
+/// Tries `shift` and then ever wider detours either side of it, keeping the first that clears
+/// `obstacles` other than `ends`, or the first try if none does.
+fn detour(shift: f64, obstacles: &[Rect], ends: [Rect; 2], make: impl Fn(f64) -> EdgePath) -> EdgePath {
+    let first = make(shift);
+    if !first.crosses(obstacles, ends) {
+        return first;
+    }
+    (1..=TRIES)
+        .flat_map(|k| vec![shift + k as f64 * DETOUR, shift - k as f64 * DETOUR])
+        .map(make)
+        .find(|path| !path.crosses(obstacles, ends))
+        .unwrap_or(first)
+}
+
+/// An edge from `goal` back to itself, out of its top and round into its right side. `lane` makes
+/// loops on the same goal bigger or smaller so they do not overlap.
+fn looped(goal: Rect, lane: f64) -> Routed {
+    let reach = BOW + lane;
+    let path = EdgePath::Curve {
+        from: (goal.x + goal.w * 0.75, goal.y),
+        ctrl: (goal.x + goal.w + reach, goal.y - reach),
+        to: (goal.x + goal.w, goal.y + goal.h * 0.25),
+    };
+    let arrow = arrow(path.last_leg());
+    Routed { path, arrow }
+}
+
+/// The path of an edge from `from` to `to`, `lane` px off to the side for parallel edges.
+/// `obstacles` are the goals on the canvas; `from` and `to` among them are not in the way.
+pub fn route(from: Rect, to: Rect, lane: f64, routing: EdgeRouting, obstacles: &[Rect]) -> Routed {
+    let path = match routing {
+        EdgeRouting::Straight if lane == 0. => {
+            EdgePath::Polyline(vec![from.clip(to.centre()), to.clip(from.centre())])
+        }
+        EdgeRouting::Straight => bowed(from, to, lane),
+        EdgeRouting::Curved => detour(BOW + lane, obstacles, [from, to], |bend| bowed(from, to, bend)),
+        EdgeRouting::Orthogonal => {
+            let ((fx, fy), (tx, ty)) = (from.centre(), to.centre());
+            let vertical = (ty - fy).abs() >= (tx - fx).abs();
+            detour(lane, obstacles, [from, to], |shift| elbow(from, to, vertical, shift))
+        }
+    };
+    let arrow = arrow(path.last_leg());
+    Routed { path, arrow }
+}
+
+/// Routes every edge at once, so parallel edges can share out lanes and each edge can keep clear
+/// of the goals that are not its ends. `None` for edges with an end missing from `goals`.
+pub fn route_edges<T: Copy + Ord + Hash>(goals: &[(T, Rect)], edges: &[(T, T)], routing: EdgeRouting) -> Vec<Option<Routed>> {
+    let rects: HashMap<T, Rect> = goals.iter().copied().collect();
+    let obstacles: Vec<Rect> = match routing {
+        EdgeRouting::Straight => vec![],
+        _ => goals.iter().map(|(_, r)| *r).collect(),
+    };
+    let lanes = lanes(edges);
+    edges
+        .iter()
+        .zip(lanes)
+        .map(|((left, right), lane)| {
+            let (from, to) = (*rects.get(left)?, *rects.get(right)?);
+            if left == right {
+                return Some(looped(from, lane));
+            }
+            Some(route(from, to, lane, routing, &obstacles))
+        })
+        .collect()
+}
+
+/// The routes last worked out, kept until the goals, the edges or the routing change. A world
+/// resource, so the renderer does not route every edge again on every frame.
+pub struct RouteCache<T> {
+    routed: Option<(Vec<(T, Rect)>, Vec<(T, T)>, EdgeRouting)>,
+    routes: Vec<Option<Routed>>,
+}
+
+impl<T> Default for RouteCache<T> {
+    fn default() -> Self {
+        RouteCache { routed: None, routes: vec![] }
+    }
+}
+
+impl<T: Copy + Ord + Hash> RouteCache<T> {
+    /// `route_edges`, unless nothing has changed since the last call.
+    pub fn routes(&mut self, goals: Vec<(T, Rect)>, edges: Vec<(T, T)>, routing: EdgeRouting) -> &[Option<Routed>] {
+        let routed = Some((goals, edges, routing));
+        if self.routed != routed {
+            if let Some((goals, edges, routing)) = &routed {
+                self.routes = route_edges(goals, edges, *routing);
+            }
+            self.routed = routed;
+        }
+        &self.routes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64) -> Rect {
+        Rect { x, y, w: 50., h: 50. }
+    }
+
+    #[test]
+    fn straight_edges_stop_at_the_boxes() {
+        let routed = route(rect(0., 0.), rect(200., 0.), 0., EdgeRouting::Straight, &[]);
+        assert_eq!(routed.path, EdgePath::Polyline(vec![(50., 25.), (200., 25.)]));
+        let [tip, left, right] = routed.arrow.unwrap();
+        assert_eq!(tip, (200., 25.));
+        assert_eq!((left, right), ((190., 30.), (190., 20.)));
+    }
+
+    #[test]
+    fn parallel_edges_get_their_own_lanes() {
+        assert_eq!(lanes(&[(1, 2), (3, 4), (1, 2)]), vec![-6., 0., 6.]);
+        // offsets follow each edge's own direction, so the same sign puts the reverse edge opposite
+        assert_eq!(lanes(&[(1, 2), (2, 1)]), vec![-6., -6.]);
+        let a = route(rect(0., 0.), rect(200., 0.), -6., EdgeRouting::Straight, &[]);
+        let b = route(rect(200., 0.), rect(0., 0.), -6., EdgeRouting::Straight, &[]);
+        match (a.path, b.path) {
+            (EdgePath::Curve { ctrl: c, .. }, EdgePath::Curve { ctrl: d, .. }) => assert_ne!(c, d),
+            other => panic!("expected two curves, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn curves_bend_round_goals_in_the_way() {
+        let (from, to) = (rect(0., 0.), rect(300., 0.));
+        let blocker = rect(110., 30.);
+        let routed = route(from, to, 0., EdgeRouting::Curved, &[blocker]);
+        assert!(!routed.path.crosses(&[blocker], [from, to]));
+    }
+
+    #[test]
+    fn orthogonal_edges_run_square_round_goals() {
+        let (from, to) = (rect(0., 0.), rect(0., 300.));
+        let blocker = rect(0., 120.);
+        let routed = route(from, to, 0., EdgeRouting::Orthogonal, &[blocker]);
+        assert!(!routed.path.crosses(&[blocker], [from, to]));
+        let points = routed.path.points();
+        assert!(points.windows(2).all(|seg| seg[0].0 == seg[1].0 || seg[0].1 == seg[1].1));
+        assert_eq!(points[0], (25., 50.));
+        assert_eq!(points[points.len() - 1], (25., 300.));
+    }
+
+    #[test]
+    fn edges_to_missing_goals_are_skipped() {
+        let goals = [(1, rect(0., 0.)), (2, rect(200., 0.))];
+        let routed = route_edges(&goals, &[(1, 2), (2, 3)], EdgeRouting::Curved);
+        assert!(routed[0].is_some());
+        assert!(routed[1].is_none());
+    }
+
+    #[test]
+    fn self_loops_go_round_the_corner() {
+        let routed = route_edges(&[(1, rect(0., 0.))], &[(1, 1)], EdgeRouting::Straight);
+        let routed = routed[0].as_ref().unwrap();
+        match routed.path {
+            EdgePath::Curve { from, ctrl, to } => {
+                assert_eq!((from, to), ((37.5, 0.), (50., 12.5)));
+                assert!(ctrl.0 > 50. && ctrl.1 < 0.);
+            }
+            ref other => panic!("expected a loop, got {:?}", other),
+        }
+        assert_eq!(routed.arrow.unwrap()[0], (50., 12.5));
+    }
+
+    #[test]
+    fn cached_routes_follow_moves() {
+        let mut cache = RouteCache::default();
+        let first = cache.routes(vec![(1, rect(0., 0.)), (2, rect(200., 0.))], vec![(1, 2)], EdgeRouting::Straight).to_vec();
+        let same = cache.routes(vec![(1, rect(0., 0.)), (2, rect(200., 0.))], vec![(1, 2)], EdgeRouting::Straight);
+        assert_eq!(same, &first[..]);
+        let moved = cache.routes(vec![(1, rect(0., 100.)), (2, rect(200., 0.))], vec![(1, 2)], EdgeRouting::Straight);
+        assert_ne!(moved, &first[..]);
+    }
+}
@@ -0,0 +1,67 @@
+use super::{ConsensusEdge, ConsensusGoal};
+use petgraph::prelude::*;
+
+/// How well `query` matches `text`, ignoring case; `None` when it does not match at all. A
+/// substring beats a scattered match, and earlier beats later. Scattered matches score higher the
+/// more of their letters are consecutive or start words.
+pub fn score(query: &str, text: &str) -> Option<u32> {
+    let (query, text) = (query.to_lowercase(), text.to_lowercase());
+    if query.is_empty() {
+        return None;
+    }
+    if let Some(at) = text.find(&query) {
+        return Some(10_000u32.saturating_sub(at as u32));
+    }
+    let mut wanted = query.chars().peekable();
+    let (mut score, mut last, mut prev) = (0, None, ' ');
+    for (i, c) in text.chars().enumerate() {
+        if wanted.peek() == Some(&c) {
+            wanted.next();
+            score += match (last, prev.is_alphanumeric()) {
+                (Some(l), _) if l + 1 == i => 5,
+                (_, false) => 3,
+                _ => 1,
+            };
+            last = Some(i);
+        }
+        prev = c;
+    }
+    if wanted.peek().is_none() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+/// The ids of every goal matching `query`, best match first.
+pub fn search(graph: &DiGraph<ConsensusGoal, ConsensusEdge>, query: &str) -> Vec<usize> {
+    let mut hits: Vec<(u32, usize)> = graph
+        .raw_nodes()
+        .iter()
+        .filter_map(|node| Some((score(query, &node.weight.st8mnt)?, node.weight.id)))
+        .collect();
+    hits.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    hits.into_iter().map(|(_, id)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_case() {
+        assert!(score("ROOT", "the root goal").is_some());
+    }
+
+    #[test]
+    fn substrings_beat_scattered_letters() {
+        assert!(score("fund", "funding") > score("fund", "find a unicorn doctor"));
+        assert!(score("fund", "funding") > score("fund", "more funding"));
+    }
+
+    #[test]
+    fn scattered_letters_must_be_in_order() {
+        assert!(score("gl", "goal").is_some());
+        assert!(score("lg", "goal").is_none());
+    }
+}
@@ -0,0 +1,193 @@
+use super::route::Rect;
+use crate::components::Pos;
+
+/// How close, in px, an edge or middle of a dragged goal has to come to another goal's to line up with it.
+const TOLERANCE: f64 = 6.;
+/// Room left between goals pushed apart on drop.
+const GAP: f64 = 10.;
+/// Rounds of pushing before we give up on goals knocking each other about.
+const ROUNDS: usize = 50;
+
+/// Where dragged goals may go. A world resource, read by the `Drag` system.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapGrid {
+    pub on: bool,
+    pub size: f64,
+}
+
+impl Default for SnapGrid {
+    fn default() -> Self {
+        SnapGrid { on: false, size: 25. }
+    }
+}
+
+/// A line a dragged goal lines up on, in graph coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Guide {
+    Vertical(f64),
+    Horizontal(f64),
+}
+
+/// The guides for the drag in progress. Written by `Drag`, drawn by the renderer.
+#[derive(Debug, Default)]
+pub struct Guides(pub Vec<Guide>);
+
+pub fn snap_to_grid(pos: Pos, size: f64) -> Pos {
+    Pos { x: (pos.x / size).round() * size, y: (pos.y / size).round() * size }
+}
+
+/// Near edge, middle and far edge of `from..from + len`.
+fn marks(from: f64, len: f64) -> [f64; 3] {
+    [from, from + len / 2., from + len]
+}
+
+/// The smallest shift within `TOLERANCE` that lines one of `from..from + len`'s marks up with one of
+/// `others`', and the line they meet on.
+fn nearest(from: f64, len: f64, others: impl Iterator<Item = (f64, f64)>) -> Option<(f64, f64)> {
+    let mine = marks(from, len);
+    let mut best: Option<(f64, f64)> = None;
+    for (o_from, o_len) in others {
+        for theirs in marks(o_from, o_len).iter() {
+            for m in mine.iter() {
+                let shift = theirs - m;
+                if shift.abs() <= TOLERANCE && best.map_or(true, |(b, _)| shift.abs() < b.abs()) {
+                    best = Some((shift, *theirs));
+                }
+            }
+        }
+    }
+    best
+}
+
+/// Nudges `moving` into line with the closest edges or middles of `others`, one axis at a time,
+/// and the guides to show for it.
+pub fn align(moving: Rect, others: &[Rect]) -> (Pos, Vec<Guide>) {
+    let mut pos = Pos { x: moving.x, y: moving.y };
+    let mut guides = vec![];
+    if let Some((shift, x)) = nearest(moving.x, moving.w, others.iter().map(|o| (o.x, o.w))) {
+        pos.x += shift;
+        guides.push(Guide::Vertical(x));
+    }
+    if let Some((shift, y)) = nearest(moving.y, moving.h, others.iter().map(|o| (o.y, o.h))) {
+        pos.y += shift;
+        guides.push(Guide::Horizontal(y));
+    }
+    (pos, guides)
+}
+
+/// How far to move `b` the shortest way so it clears `a` by `GAP`, unless it already does.
+fn push(a: Rect, b: Rect) -> Option<(f64, f64)> {
+    let right = a.x + a.w + GAP - b.x;
+    let left = b.x + b.w + GAP - a.x;
+    let down = a.y + a.h + GAP - b.y;
+    let up = b.y + b.h + GAP - a.y;
+    if right <= 0. || left <= 0. || down <= 0. || up <= 0. {
+        return None;
+    }
+    let least = right.min(left).min(down).min(up);
+    Some(if least == right {
+        (right, 0.)
+    } else if least == left {
+        (-left, 0.)
+    } else if least == down {
+        (0., down)
+    } else {
+        (0., -up)
+    })
+}
+
+/// Pushes goals off `rects[dropped]`, then off whatever they were pushed into, and so on. The
+/// dropped goal and the `pinned` ones stay where they are, and goals already on top of each other
+/// elsewhere are left be. Returns which goals moved.
+pub fn separate(rects: &mut [Rect], dropped: usize, pinned: &[bool]) -> Vec<usize> {
+    let mut moved: Vec<usize> = vec![];
+    let mut pushers = vec![dropped];
+    for _ in 0..ROUNDS {
+        let mut pushed: Vec<usize> = vec![];
+        for i in pushers {
+            for j in 0..rects.len() {
+                if j == dropped || j == i || pinned[j] {
+                    continue;
+                }
+                if let Some((dx, dy)) = push(rects[i], rects[j]) {
+                    rects[j].x += dx;
+                    rects[j].y += dy;
+                    if !moved.contains(&j) {
+                        moved.push(j);
+                    }
+                    if !pushed.contains(&j) {
+                        pushed.push(j);
+                    }
+                }
+            }
+        }
+        if pushed.is_empty() {
+            break;
+        }
+        pushers = pushed;
+    }
+    moved
+}
+
+/// Where `rect` goes to clear all of `occupied` by `GAP`, moving straight down past whatever it
+/// lands on.
+pub fn clear_of(mut rect: Rect, occupied: &[Rect]) -> Pos {
+    while let Some(hit) = occupied.iter().find(|o| push(**o, rect).is_some()) {
+        rect.y = hit.y + hit.h + GAP;
+    }
+    Pos { x: rect.x, y: rect.y }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64) -> Rect {
+        Rect { x, y, w: 50., h: 50. }
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_grid_point() {
+        assert_eq!(snap_to_grid(Pos { x: 37., y: 12. }, 25.), Pos { x: 25., y: 0. });
+        assert_eq!(snap_to_grid(Pos { x: -13., y: 40. }, 25.), Pos { x: -25., y: 50. });
+    }
+
+    #[test]
+    fn lines_up_with_nearby_edges_and_middles() {
+        // left edges 3px apart, and the top of one 3px off the middle of a shorter one
+        let others = [rect(100., 0.), Rect { x: 400., y: 400., w: 50., h: 30. }];
+        let (pos, guides) = align(rect(103., 412.), &others);
+        assert_eq!(pos, Pos { x: 100., y: 415. });
+        assert_eq!(guides, vec![Guide::Vertical(100.), Guide::Horizontal(415.)]);
+    }
+
+    #[test]
+    fn leaves_goals_far_from_others_alone() {
+        let (pos, guides) = align(rect(120., 300.), &[rect(0., 0.)]);
+        assert_eq!(pos, Pos { x: 120., y: 300. });
+        assert!(guides.is_empty());
+    }
+
+    #[test]
+    fn pushes_goals_off_the_dropped_one_and_each_other() {
+        // 1 overlaps the dropped 0 and gets pushed into 2; 3 and 4 overlap but nothing touched them
+        let mut rects = vec![rect(0., 0.), rect(40., 5.), rect(100., 5.), rect(500., 500.), rect(510., 500.)];
+        let moved = separate(&mut rects, 0, &[false; 5]);
+        assert_eq!(moved, vec![1, 2]);
+        assert_eq!(rects, vec![rect(0., 0.), rect(60., 5.), rect(120., 5.), rect(500., 500.), rect(510., 500.)]);
+    }
+
+    #[test]
+    fn leaves_pinned_goals_where_they_are() {
+        let mut rects = vec![rect(0., 0.), rect(40., 5.)];
+        assert!(separate(&mut rects, 0, &[false, true]).is_empty());
+        assert_eq!(rects[1], rect(40., 5.));
+    }
+
+    #[test]
+    fn moves_down_until_clear() {
+        let occupied = [rect(0., 0.), rect(0., 60.), rect(300., 0.)];
+        assert_eq!(clear_of(rect(10., 20.), &occupied), Pos { x: 10., y: 120. });
+        assert_eq!(clear_of(rect(100., 0.), &occupied), Pos { x: 100., y: 0. });
+    }
+}
@@ -0,0 +1,124 @@
+use super::forest;
+use super::{ConsensusEdge, ConsensusGoal};
+use petgraph::prelude::*;
+use petgraph::visit::{DfsPostOrder, VisitMap};
+
+/// How goal weights are spread.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Distribution {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub median: f32,
+}
+
+impl Distribution {
+    fn of(mut values: Vec<f32>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let len = values.len();
+        let median = if len % 2 == 1 {
+            values[len / 2]
+        } else {
+            (values[len / 2 - 1] + values[len / 2]) / 2.
+        };
+        Self {
+            min: values[0],
+            max: values[len - 1],
+            mean: values.iter().sum::<f32>() / len as f32,
+            median,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub goals: usize,
+    pub edges: usize,
+    /// Roots as the layout sees them, see `forest::trees`.
+    pub roots: usize,
+    /// Goals without sub-goals.
+    pub leaves: usize,
+    /// Edges on the longest path down from any goal. Edges closing a cycle are not followed.
+    pub max_depth: usize,
+    /// Sub-goals per goal that has any.
+    pub fan_out: f32,
+    pub plugged: usize,
+    pub unplugged: usize,
+    pub weights: Distribution,
+    /// Goals in each weakly connected component, largest first.
+    pub components: Vec<usize>,
+}
+
+fn max_depth(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> usize {
+    let mut height: Vec<Option<usize>> = vec![None; graph.node_count()];
+    let mut dfs = DfsPostOrder::empty(graph);
+    for start in graph.node_indices() {
+        if dfs.discovered.is_visited(&start) {
+            continue;
+        }
+        dfs.move_to(start);
+        while let Some(idx) = dfs.next(graph) {
+            let below = graph.neighbors(idx).filter_map(|child| height[child.index()]).map(|h| h + 1).max();
+            height[idx.index()] = Some(below.unwrap_or(0));
+        }
+    }
+    height.into_iter().flatten().max().unwrap_or(0)
+}
+
+pub fn stats(graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Stats {
+    let trees = forest::trees(graph);
+    let mut components: Vec<usize> = trees.iter().map(|tree| tree.order.len()).collect();
+    components.sort_by(|a, b| b.cmp(a));
+
+    let leaves = graph.node_indices().filter(|idx| graph.neighbors(*idx).next().is_none()).count();
+    let parents = graph.node_count() - leaves;
+    let plugged = graph.raw_nodes().iter().filter(|node| node.weight.plugged).count();
+
+    Stats {
+        goals: graph.node_count(),
+        edges: graph.edge_count(),
+        roots: trees.iter().map(|tree| tree.roots.len()).sum(),
+        leaves,
+        max_depth: max_depth(graph),
+        fan_out: if parents == 0 { 0. } else { graph.edge_count() as f32 / parents as f32 },
+        plugged,
+        unplugged: graph.node_count() - plugged,
+        weights: Distribution::of(graph.raw_nodes().iter().map(|node| node.weight.weight).collect()),
+        components,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::{fixture, weighted};
+    use super::*;
+
+    #[test]
+    fn empty_graph() {
+        assert_eq!(stats(&DiGraph::new()), Stats::default());
+    }
+
+    #[test]
+    fn counts_a_forest() {
+        // 0 -> 1 -> 2, 0 -> 3, and 4 -> 5 on its own
+        let mut graph = weighted(&[1., 2., 3., 4., 5., 6.], &[(0, 1, 1.), (1, 2, 1.), (0, 3, 1.), (4, 5, 1.)]);
+        graph[NodeIndex::new(2)].plugged = true;
+        let stats = stats(&graph);
+        assert_eq!((stats.goals, stats.edges), (6, 4));
+        assert_eq!((stats.roots, stats.leaves), (2, 3));
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!(stats.fan_out, 4. / 3.);
+        assert_eq!((stats.plugged, stats.unplugged), (1, 5));
+        assert_eq!(stats.components, vec![4, 2]);
+        assert_eq!(stats.weights, Distribution { min: 1., max: 6., mean: 3.5, median: 3.5 });
+    }
+
+    #[test]
+    fn depth_survives_cycles() {
+        let graph = fixture(3, &[(0, 1), (1, 2), (2, 0)]);
+        assert_eq!(stats(&graph).max_depth, 2);
+    }
+}
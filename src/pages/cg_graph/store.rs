@@ -0,0 +1,125 @@
+use super::io::GraphFile;
+use serde_json::{json, Value};
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+/// Bump this, and teach `migrate` the step up, whenever a saved `GraphFile` would no longer
+/// deserialize as it is.
+pub const SCHEMA: u64 = 1;
+
+#[derive(Debug)]
+pub enum StoreError {
+    Json(serde_json::Error),
+    /// Saved by a newer client than this one.
+    Newer(u64),
+    Browser(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use StoreError::*;
+        match self {
+            Json(e) => write!(f, "bad save: {}", e),
+            Newer(schema) => write!(f, "save has schema {}, we only know up to {}", schema, SCHEMA),
+            Browser(e) => write!(f, "browser storage refused: {}", e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(err: serde_json::Error) -> Self {
+        StoreError::Json(err)
+    }
+}
+
+impl From<JsValue> for StoreError {
+    fn from(err: JsValue) -> Self {
+        StoreError::Browser(format!("{:?}", err))
+    }
+}
+
+fn key(graph: &str) -> String {
+    format!("cg_graph/{}", graph)
+}
+
+/// Takes a save one schema up from `from`.
+fn migrate(from: u64, save: Value) -> Value {
+    match from {
+        // before saves were versioned they were the bare file, as exported to .json
+        0 => json!({ "schema": 1, "file": save }),
+        _ => unreachable!("no save has schema {}", from),
+    }
+}
+
+pub fn encode(file: &GraphFile) -> Result<String, StoreError> {
+    Ok(serde_json::to_string(&json!({ "schema": SCHEMA, "file": file }))?)
+}
+
+pub fn decode(src: &str) -> Result<GraphFile, StoreError> {
+    let mut save: Value = serde_json::from_str(src)?;
+    let schema = save.get("schema").and_then(Value::as_u64).unwrap_or(0);
+    if schema > SCHEMA {
+        return Err(StoreError::Newer(schema));
+    }
+    for from in schema..SCHEMA {
+        save = migrate(from, save);
+    }
+    Ok(serde_json::from_value(save["file"].take())?)
+}
+
+fn local_storage() -> Result<web_sys::Storage, StoreError> {
+    seed::window()
+        .local_storage()?
+        .ok_or_else(|| StoreError::Browser("no local storage".to_string()))
+}
+
+/// Keeps `file` in the browser under the graph's id, replacing any older save.
+pub fn save(graph: &str, file: &GraphFile) -> Result<(), StoreError> {
+    local_storage()?.set_item(&key(graph), &encode(file)?)?;
+    Ok(())
+}
+
+/// The last save of the graph, if this browser has one.
+pub fn restore(graph: &str) -> Result<Option<GraphFile>, StoreError> {
+    match local_storage()?.get_item(&key(graph))? {
+        Some(src) => decode(&src).map(Some),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::io::Placement;
+    use super::super::{ConsensusGoal, GraphDocument};
+    use super::*;
+
+    fn file() -> GraphFile {
+        GraphFile {
+            graph: GraphDocument::new(vec![ConsensusGoal { id: 3, root: true, ..Default::default() }], vec![]),
+            positions: vec![Placement { id: 3, x: 12., y: 34. }],
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let back = decode(&encode(&file()).unwrap()).unwrap();
+        assert_eq!(back.graph.goals[0].id, 3);
+        assert_eq!((back.positions[0].x, back.positions[0].y), (12., 34.));
+    }
+
+    #[test]
+    fn migrates_unversioned_saves() {
+        let old = serde_json::to_string(&file()).unwrap();
+        let back = decode(&old).unwrap();
+        assert_eq!(back.positions[0].id, 3);
+    }
+
+    #[test]
+    fn refuses_saves_from_the_future() {
+        let src = json!({ "schema": SCHEMA + 1, "file": file() }).to_string();
+        match decode(&src) {
+            Err(StoreError::Newer(schema)) => assert_eq!(schema, SCHEMA + 1),
+            other => panic!("expected Newer, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use super::route::{route_edges, EdgePath, EdgeRouting, Rect};
+use super::{HEIGHT, WIDTH};
+use crate::components::*;
+use crate::systems::{lineage_colour, node_fill, Camera, Selection};
+use specs::prelude::*;
+use std::fmt::Write;
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn opacity(rank: Option<&Lineage>) -> &'static str {
+    if rank == Some(&Lineage::Dimmed) {
+        " opacity=\"0.25\""
+    } else {
+        ""
+    }
+}
+
+fn frame(out: &mut String, pos: &Pos, dim: &Dimension, pad: f64, colour: &str) {
+    writeln!(
+        out,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="{}"/>"#,
+        pos.x - pad,
+        pos.y - pad,
+        dim.w + 2. * pad,
+        dim.h + 2. * pad,
+        colour
+    )
+    .unwrap();
+}
+
+/// The canvas as the renderer last drew it, as a standalone SVG document.
+pub fn render(world: &World) -> String {
+    let (dims, poss, texts, edges, ents, states) = (
+        world.read_storage::<Dimension>(),
+        world.read_storage::<Pos>(),
+        world.read_storage::<Text>(),
+        world.read_storage::<Edge>(),
+        world.entities(),
+        world.read_storage::<Interactable>(),
+    );
+    let (consensus, plugged, open, hidden, in_cycle, lineage, collapsed, rerouted) = (
+        world.read_storage::<Consensus>(),
+        world.read_storage::<Plugged>(),
+        world.read_storage::<OpenLeaves>(),
+        world.read_storage::<Hidden>(),
+        world.read_storage::<InCycle>(),
+        world.read_storage::<Lineage>(),
+        world.read_storage::<Collapsed>(),
+        world.read_storage::<Rerouted>(),
+    );
+    let pinned = world.read_storage::<Pinned>();
+    let routing = *world.read_resource::<EdgeRouting>();
+    let camera = world.read_resource::<Camera>();
+    let selection = world.read_resource::<Selection>();
+
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="10">"#,
+        w = WIDTH,
+        h = HEIGHT
+    )
+    .unwrap();
+    writeln!(out, r#"<g transform="translate({} {})">"#, -camera.x, -camera.y).unwrap();
+
+    for (ent, dim, pos, _) in (&ents, &dims, &poss, !&hidden).join() {
+        let rank = lineage.get(ent);
+        let fill = node_fill(states.get(ent).unwrap_or(&Interactable::Nothing), consensus.get(ent));
+        writeln!(out, "<g{}>", opacity(rank)).unwrap();
+        writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+            pos.x, pos.y, dim.w, dim.h, fill
+        )
+        .unwrap();
+        if let Some(Plugged(false)) = plugged.get(ent) {
+            frame(&mut out, pos, dim, 0., "#FF8800");
+        }
+        if in_cycle.contains(ent) {
+            frame(&mut out, pos, dim, 6., "#FF0000");
+        }
+        if let Some(OpenLeaves(n)) = open.get(ent).filter(|n| n.0 > 0) {
+            writeln!(out, r#"<text x="{}" y="{}">{} open</text>"#, pos.x, pos.y - 4., n).unwrap();
+        }
+        // the count of folded goals is part of the picture, the handles and toggle are editor chrome
+        if let Some(Collapsed(n)) = collapsed.get(ent) {
+            writeln!(out, r##"<text x="{}" y="{}" fill="#FFFFFF">+{}</text>"##, pos.x + 2., pos.y + dim.h / 2., n).unwrap();
+        }
+        if pinned.contains(ent) {
+            writeln!(out, r#"<rect x="{}" y="{}" width="4" height="4"/>"#, pos.x + 2., pos.y + 2.).unwrap();
+        }
+        if let Some(txt) = texts.get(ent) {
+            writeln!(out, r#"<text x="{}" y="{}">{}</text>"#, pos.x, pos.y + dim.h + 12., escape(&txt.st)).unwrap();
+        }
+        if selection.0 == Some(ent) {
+            frame(&mut out, pos, dim, 3., "#000000");
+        }
+        if let Some(colour) = lineage_colour(rank) {
+            frame(&mut out, pos, dim, 3., colour);
+        }
+        writeln!(out, "</g>").unwrap();
+    }
+
+    let goals: Vec<(Entity, Rect)> = (&ents, &poss, &dims, !&hidden)
+        .join()
+        .map(|(ent, pos, dim, _)| (ent, Rect::of(pos, dim)))
+        .collect();
+    let links: Vec<(Entity, (Entity, Entity))> = (&ents, &edges)
+        .join()
+        .map(|(ent, edge)| (ent, rerouted.get(ent).map_or((edge.left, edge.right), |r| (r.left, r.right))))
+        .collect();
+    let ends: Vec<(Entity, Entity)> = links.iter().map(|(_, ends)| *ends).collect();
+    for ((ent, _), routed) in links.iter().zip(route_edges(&goals, &ends, routing)) {
+        let routed = match routed {
+            Some(routed) => routed,
+            None => continue,
+        };
+        let rank = lineage.get(*ent);
+        let (colour, width) = lineage_colour(rank).map_or(("#000000", 1), |c| (c, 3));
+        let d = match &routed.path {
+            EdgePath::Polyline(points) => {
+                let mut d = format!("M {} {}", points[0].0, points[0].1);
+                for (x, y) in &points[1..] {
+                    write!(d, " L {} {}", x, y).unwrap();
+                }
+                d
+            }
+            EdgePath::Curve { from, ctrl, to } => {
+                format!("M {} {} Q {} {} {} {}", from.0, from.1, ctrl.0, ctrl.1, to.0, to.1)
+            }
+        };
+        writeln!(out, "<g{}>", opacity(rank)).unwrap();
+        writeln!(out, r#"<path d="{}" fill="none" stroke="{}" stroke-width="{}"/>"#, d, colour, width).unwrap();
+        if let Some([tip, a, b]) = routed.arrow {
+            writeln!(
+                out,
+                r#"<polygon points="{},{} {},{} {},{}" fill="{}"/>"#,
+                tip.0, tip.1, a.0, a.1, b.0, b.1, colour
+            )
+            .unwrap();
+        }
+        writeln!(out, "</g>").unwrap();
+    }
+
+    writeln!(out, "</g>").unwrap();
+    writeln!(out, "</svg>").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Dimension>();
+        world.register::<Pos>();
+        world.register::<Text>();
+        world.register::<Edge>();
+        world.register::<Interactable>();
+        world.register::<Consensus>();
+        world.register::<Plugged>();
+        world.register::<OpenLeaves>();
+        world.register::<Hidden>();
+        world.register::<InCycle>();
+        world.register::<Lineage>();
+        world.register::<Collapsed>();
+        world.register::<Rerouted>();
+        world.register::<Pinned>();
+        world.insert(Camera::default());
+        world.insert(Selection::default());
+        world.insert(EdgeRouting::default());
+        world
+    }
+
+    fn goal(world: &mut World, x: f64, y: f64, st: &str) -> Entity {
+        world
+            .create_entity()
+            .with(Pos { x, y })
+            .with(Dimension { w: 50., h: 50. })
+            .with(Text { st: st.to_string() })
+            .build()
+    }
+
+    #[test]
+    fn draws_goals_and_edges_where_they_are() {
+        let mut world = world();
+        let left = goal(&mut world, 10., 20., "root");
+        let right = goal(&mut world, 110., 220., "leaf");
+        world.create_entity().with(Edge { left, right }).build();
+        let svg = render(&world);
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains(r##"<rect x="10" y="20" width="50" height="50" fill="#000000"/>"##));
+        // from the bottom of the root's box into the top of the leaf's, with an arrowhead
+        assert!(svg.contains(r#"<path d="M 47.5 70 L 122.5 220""#));
+        assert!(svg.contains(r#"<polygon points="122.5,220 "#));
+        assert!(svg.contains(">root</text>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn leaves_out_hidden_goals_and_their_edges() {
+        let mut world = world();
+        let left = goal(&mut world, 0., 0., "shown");
+        let right = goal(&mut world, 0., 0., "folded away");
+        world.write_storage::<Hidden>().insert(right, Hidden).unwrap();
+        world.create_entity().with(Edge { left, right }).build();
+        let svg = render(&world);
+        assert!(!svg.contains("folded away"));
+        assert!(!svg.contains("<path"));
+    }
+
+    #[test]
+    fn leaves_out_handles_and_toggles() {
+        let mut world = world();
+        let folded = goal(&mut world, 0., 0., "folded");
+        goal(&mut world, 100., 0., "open");
+        world.write_storage::<Collapsed>().insert(folded, Collapsed(3)).unwrap();
+        let svg = render(&world);
+        assert!(!svg.contains(r##"fill="#FFFFFF"/>"##));
+        assert!(!svg.contains(">-</text>") && !svg.contains(">+</text>"));
+        assert!(svg.contains(">+3</text>"));
+    }
+
+    #[test]
+    fn escapes_statements() {
+        let mut world = world();
+        goal(&mut world, 0., 0., "<cheap & \"fast\">");
+        assert!(render(&world).contains("&lt;cheap &amp; &quot;fast&quot;&gt;"));
+    }
+}
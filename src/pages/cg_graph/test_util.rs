@@ -0,0 +1,54 @@
+use super::world_index;
+use super::{ConsensusEdge, ConsensusGoal, GraphWorldIndex};
+use crate::components::*;
+use petgraph::prelude::*;
+use specs::prelude::*;
+
+/// Goals `0..weights.len()` weighing `weights`, and an edge for each `(left, right, weight)`. Ids
+/// match node and edge indices.
+pub fn weighted(weights: &[f32], edges: &[(usize, usize, f32)]) -> DiGraph<ConsensusGoal, ConsensusEdge> {
+    let mut graph = DiGraph::new();
+    for (id, weight) in weights.iter().enumerate() {
+        graph.add_node(ConsensusGoal { id, weight: *weight, ..Default::default() });
+    }
+    for (id, (left, right, weight)) in edges.iter().enumerate() {
+        let edge = ConsensusEdge { id, left: *left, right: *right, weight: *weight, ..Default::default() };
+        graph.add_edge(NodeIndex::new(*left), NodeIndex::new(*right), edge);
+    }
+    graph
+}
+
+/// `count` weightless goals and weightless edges between them.
+pub fn fixture(count: usize, edges: &[(usize, usize)]) -> DiGraph<ConsensusGoal, ConsensusEdge> {
+    let edges: Vec<(usize, usize, f32)> = edges.iter().map(|(left, right)| (*left, *right, 0.)).collect();
+    weighted(&vec![0.; count], &edges)
+}
+
+/// A world with what `world_index` needs to draw a graph, and nothing drawn yet.
+pub fn world() -> World {
+    let mut world = World::new();
+    world.register::<Pos>();
+    world.register::<Dimension>();
+    world.register::<Origin>();
+    world.register::<Interactable>();
+    world.register::<Text>();
+    world.register::<Plugged>();
+    world.register::<Edge>();
+    world.register::<Hidden>();
+    world.register::<Foldable>();
+    world.register::<Collapsed>();
+    world.register::<Rerouted>();
+    world.insert(GraphWorldIndex::default());
+    world
+}
+
+/// `fixture` drawn in a fresh `world`, each goal's statement its id.
+pub fn drawn(count: usize, edges: &[(usize, usize)]) -> (World, DiGraph<ConsensusGoal, ConsensusEdge>) {
+    let mut graph = fixture(count, edges);
+    for goal in graph.node_weights_mut() {
+        goal.st8mnt = goal.id.to_string();
+    }
+    let mut world = world();
+    world_index::reconcile(&mut world, &DiGraph::new(), &graph, |_| Pos::default());
+    (world, graph)
+}
@@ -0,0 +1,433 @@
+use super::analysis;
+use super::collapse::Folding;
+use super::{ConsensusEdge, ConsensusGoal, RAD};
+use crate::components::*;
+use petgraph::prelude::*;
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// Ties the nodes and edges of `Model::pet` to the entities that draw them.
+#[derive(Debug, Default)]
+pub struct GraphWorldIndex {
+    node_to_ent: HashMap<NodeIndex, Entity>,
+    ent_to_node: HashMap<Entity, NodeIndex>,
+    edge_to_ent: HashMap<EdgeIndex, Entity>,
+    ent_to_edge: HashMap<Entity, EdgeIndex>,
+}
+
+impl GraphWorldIndex {
+    pub fn insert_node(&mut self, idx: NodeIndex, ent: Entity) {
+        self.node_to_ent.insert(idx, ent);
+        self.ent_to_node.insert(ent, idx);
+    }
+
+    pub fn insert_edge(&mut self, idx: EdgeIndex, ent: Entity) {
+        self.edge_to_ent.insert(idx, ent);
+        self.ent_to_edge.insert(ent, idx);
+    }
+
+    pub fn node_entity(&self, idx: NodeIndex) -> Option<Entity> {
+        self.node_to_ent.get(&idx).copied()
+    }
+
+    pub fn edge_entity(&self, idx: EdgeIndex) -> Option<Entity> {
+        self.edge_to_ent.get(&idx).copied()
+    }
+
+    pub fn entity_node(&self, ent: Entity) -> Option<NodeIndex> {
+        self.ent_to_node.get(&ent).copied()
+    }
+
+    pub fn entity_edge(&self, ent: Entity) -> Option<EdgeIndex> {
+        self.ent_to_edge.get(&ent).copied()
+    }
+
+    fn remove_node(&mut self, idx: NodeIndex) -> Option<Entity> {
+        let ent = self.node_to_ent.remove(&idx)?;
+        self.ent_to_node.remove(&ent);
+        Some(ent)
+    }
+
+    fn remove_edge(&mut self, idx: EdgeIndex) -> Option<Entity> {
+        let ent = self.edge_to_ent.remove(&idx)?;
+        self.ent_to_edge.remove(&ent);
+        Some(ent)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeIndex, Entity)> + '_ {
+        self.node_to_ent.iter().map(|(idx, ent)| (*idx, *ent))
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (EdgeIndex, Entity)> + '_ {
+        self.edge_to_ent.iter().map(|(idx, ent)| (*idx, *ent))
+    }
+}
+
+pub fn node_dimension() -> Dimension {
+    Dimension {
+        w: RAD as f64,
+        h: RAD as f64,
+    }
+}
+
+pub fn node_pos(world: &World, idx: NodeIndex) -> Option<Pos> {
+    let ent = world.read_resource::<GraphWorldIndex>().node_entity(idx)?;
+    world.read_storage::<Pos>().get(ent).copied()
+}
+
+pub fn set_node_pos(world: &mut World, idx: NodeIndex, pos: Pos) {
+    let ent = world.read_resource::<GraphWorldIndex>().node_entity(idx);
+    if let Some(ent) = ent {
+        world.write_storage::<Pos>().insert(ent, pos).expect("indexed entity is alive");
+    }
+}
+
+/// Stores each goal's score on its entity and sizes it by its share, between half and one and a
+/// half times the default.
+pub fn apply_scores(world: &World, scores: &[f32], shares: &[f32]) {
+    let index = world.read_resource::<GraphWorldIndex>();
+    let (mut consensus, mut dims) = (world.write_storage::<Consensus>(), world.write_storage::<Dimension>());
+    for (idx, ent) in index.nodes() {
+        let (score, share) = (scores[idx.index()], shares[idx.index()]);
+        consensus.insert(ent, Consensus { score, share }).expect("indexed entity is alive");
+        let side = RAD as f64 * (0.5 + share as f64);
+        dims.insert(ent, Dimension { w: side, h: side }).expect("indexed entity is alive");
+    }
+}
+
+pub fn apply_open_leaves(world: &World, counts: &[usize]) {
+    let index = world.read_resource::<GraphWorldIndex>();
+    let mut open = world.write_storage::<OpenLeaves>();
+    for (idx, ent) in index.nodes() {
+        open.insert(ent, OpenLeaves(counts[idx.index()])).expect("indexed entity is alive");
+    }
+}
+
+/// Hides the goals that are not `visible`, and so the edges touching them.
+pub fn apply_visibility(world: &World, visible: &[bool]) {
+    let index = world.read_resource::<GraphWorldIndex>();
+    let mut hidden = world.write_storage::<Hidden>();
+    for (idx, ent) in index.nodes() {
+        if visible[idx.index()] {
+            hidden.remove(ent);
+        } else {
+            hidden.insert(ent, Hidden).expect("indexed entity is alive");
+        }
+    }
+}
+
+pub fn apply_cycles(world: &World, members: &[bool]) {
+    let index = world.read_resource::<GraphWorldIndex>();
+    let mut in_cycle = world.write_storage::<InCycle>();
+    for (idx, ent) in index.nodes() {
+        if members[idx.index()] {
+            in_cycle.insert(ent, InCycle).expect("indexed entity is alive");
+        } else {
+            in_cycle.remove(ent);
+        }
+    }
+}
+
+/// Ranks every goal and edge against the `focus` goal, or clears the ranking without one. Edges
+/// count as part of the lineage when they lie on a path through the focus.
+pub fn apply_lineage(world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, focus: Option<NodeIndex>) {
+    let index = world.read_resource::<GraphWorldIndex>();
+    let mut lineage = world.write_storage::<Lineage>();
+    let focus = match focus {
+        Some(focus) => focus,
+        None => {
+            for ent in index.nodes().map(|(_, ent)| ent).chain(index.edges().map(|(_, ent)| ent)) {
+                lineage.remove(ent);
+            }
+            return;
+        }
+    };
+    let (up, down) = (analysis::ancestors(graph, focus), analysis::descendants(graph, focus));
+    let rank = |idx: NodeIndex| match (idx == focus, up[idx.index()], down[idx.index()]) {
+        (true, _, _) => Lineage::Focus,
+        (_, true, _) => Lineage::Ancestor,
+        (_, _, true) => Lineage::Descendant,
+        _ => Lineage::Dimmed,
+    };
+    for (idx, ent) in index.nodes() {
+        lineage.insert(ent, rank(idx)).expect("indexed entity is alive");
+    }
+    for (idx, ent) in index.edges() {
+        let (left, right) = graph.edge_endpoints(idx).expect("indexed edge is in the graph");
+        let edge_rank = match (rank(left), rank(right)) {
+            (Lineage::Focus, _) | (Lineage::Descendant, _) => Lineage::Descendant,
+            (_, Lineage::Focus) | (_, Lineage::Ancestor) => Lineage::Ancestor,
+            _ => Lineage::Dimmed,
+        };
+        lineage.insert(ent, edge_rank).expect("indexed entity is alive");
+    }
+}
+
+/// Badges the collapsed goals and points the edges into their insides at them instead. Goals with
+/// sub-goals are marked as ones that can be collapsed.
+pub fn apply_folding(world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>, folding: &Folding) {
+    let index = world.read_resource::<GraphWorldIndex>();
+    let (mut foldable, mut collapsed) = (world.write_storage::<Foldable>(), world.write_storage::<Collapsed>());
+    for (idx, ent) in index.nodes() {
+        if graph.neighbors(idx).next().is_some() {
+            foldable.insert(ent, Foldable).expect("indexed entity is alive");
+        } else {
+            foldable.remove(ent);
+        }
+        match folding.inside[idx.index()] {
+            0 => collapsed.remove(ent),
+            n => collapsed.insert(ent, Collapsed(n)).expect("indexed entity is alive"),
+        };
+    }
+    let mut rerouted = world.write_storage::<Rerouted>();
+    let stand_in = |idx: NodeIndex| folding.surrogate[idx.index()].unwrap_or(idx);
+    for (idx, ent) in index.edges() {
+        let (left, right) = graph.edge_endpoints(idx).expect("indexed edge is in the graph");
+        let (to_left, to_right) = (stand_in(left), stand_in(right));
+        let moved = (to_left, to_right) != (left, right) && to_left != to_right;
+        match (moved, index.node_entity(to_left), index.node_entity(to_right)) {
+            (true, Some(left), Some(right)) => {
+                rerouted.insert(ent, Rerouted { left, right }).expect("indexed entity is alive");
+            }
+            _ => {
+                rerouted.remove(ent);
+            }
+        }
+    }
+}
+
+/// The components that mirror a goal's fields.
+fn sync_goal(world: &World, ent: Entity, goal: &ConsensusGoal) {
+    world
+        .write_storage::<Text>()
+        .insert(ent, Text { st: goal.st8mnt.clone() })
+        .expect("goal entity is alive");
+    world
+        .write_storage::<Plugged>()
+        .insert(ent, Plugged(goal.plugged))
+        .expect("goal entity is alive");
+}
+
+fn spawn_node(world: &mut World, goal: &ConsensusGoal, pos: Pos) -> Entity {
+    let ent = world
+        .create_entity()
+        .with(pos)
+        .with(node_dimension())
+        .with(Origin::Center)
+        .with(Interactable::default())
+        .build();
+    sync_goal(world, ent, goal);
+    ent
+}
+
+fn link(world: &World, left: NodeIndex, right: NodeIndex) -> Edge {
+    let index = world.read_resource::<GraphWorldIndex>();
+    Edge {
+        left: index.node_entity(left).expect("every goal has an entity"),
+        right: index.node_entity(right).expect("every goal has an entity"),
+    }
+}
+
+pub fn add_node(
+    world: &mut World,
+    graph: &mut DiGraph<ConsensusGoal, ConsensusEdge>,
+    goal: ConsensusGoal,
+    pos: Pos,
+) -> NodeIndex {
+    let ent = spawn_node(world, &goal, pos);
+    let idx = graph.add_node(goal);
+    world.write_resource::<GraphWorldIndex>().insert_node(idx, ent);
+    idx
+}
+
+pub fn update_node(
+    world: &mut World,
+    graph: &mut DiGraph<ConsensusGoal, ConsensusEdge>,
+    idx: NodeIndex,
+    goal: ConsensusGoal,
+) {
+    let ent = world.read_resource::<GraphWorldIndex>().node_entity(idx);
+    if let Some(ent) = ent {
+        sync_goal(world, ent, &goal);
+    }
+    graph[idx] = goal;
+}
+
+/// Removes the goal along with its edges. `DiGraph` moves its last node into the freed index, so
+/// the index is patched to follow it.
+pub fn remove_node(
+    world: &mut World,
+    graph: &mut DiGraph<ConsensusGoal, ConsensusEdge>,
+    idx: NodeIndex,
+) -> Option<ConsensusGoal> {
+    while let Some(edge) = graph.first_edge(idx, Outgoing).or_else(|| graph.first_edge(idx, Incoming)) {
+        remove_edge(world, graph, edge);
+    }
+    let last = NodeIndex::new(graph.node_count().checked_sub(1)?);
+    let goal = graph.remove_node(idx)?;
+    let mut index = world.write_resource::<GraphWorldIndex>();
+    let ent = index.remove_node(idx);
+    if last != idx {
+        if let Some(moved) = index.remove_node(last) {
+            index.insert_node(idx, moved);
+        }
+    }
+    drop(index);
+    if let Some(ent) = ent {
+        world.delete_entity(ent).expect("indexed entity is alive");
+    }
+    Some(goal)
+}
+
+pub fn add_edge(
+    world: &mut World,
+    graph: &mut DiGraph<ConsensusGoal, ConsensusEdge>,
+    left: NodeIndex,
+    right: NodeIndex,
+    edge: ConsensusEdge,
+) -> EdgeIndex {
+    let link = link(world, left, right);
+    let ent = world.create_entity().with(link).build();
+    let idx = graph.add_edge(left, right, edge);
+    world.write_resource::<GraphWorldIndex>().insert_edge(idx, ent);
+    idx
+}
+
+/// Same index shuffle as `remove_node`, for edges.
+pub fn remove_edge(
+    world: &mut World,
+    graph: &mut DiGraph<ConsensusGoal, ConsensusEdge>,
+    idx: EdgeIndex,
+) -> Option<ConsensusEdge> {
+    let last = EdgeIndex::new(graph.edge_count().checked_sub(1)?);
+    let edge = graph.remove_edge(idx)?;
+    let mut index = world.write_resource::<GraphWorldIndex>();
+    let ent = index.remove_edge(idx);
+    if last != idx {
+        if let Some(moved) = index.remove_edge(last) {
+            index.insert_edge(idx, moved);
+        }
+    }
+    drop(index);
+    if let Some(ent) = ent {
+        world.delete_entity(ent).expect("indexed entity is alive");
+    }
+    Some(edge)
+}
+
+/// Brings the world from drawing `old` to drawing `new`. Goals and edges are matched on their ids:
+/// matches keep their entity (and so where the user dragged them), the rest are created at
+/// `place` or deleted.
+pub fn reconcile(
+    world: &mut World,
+    old: &DiGraph<ConsensusGoal, ConsensusEdge>,
+    new: &DiGraph<ConsensusGoal, ConsensusEdge>,
+    mut place: impl FnMut(NodeIndex) -> Pos,
+) {
+    let old_index = std::mem::take(&mut *world.write_resource::<GraphWorldIndex>());
+    let mut stale_nodes: HashMap<usize, Entity> = old
+        .node_indices()
+        .filter_map(|i| old_index.node_entity(i).map(|ent| (old[i].id, ent)))
+        .collect();
+    let mut stale_edges: HashMap<usize, Entity> = old
+        .edge_indices()
+        .filter_map(|i| old_index.edge_entity(i).map(|ent| (old[i].id, ent)))
+        .collect();
+
+    let mut index = GraphWorldIndex::default();
+    for idx in new.node_indices() {
+        let goal = &new[idx];
+        let ent = match stale_nodes.remove(&goal.id) {
+            Some(ent) => {
+                sync_goal(world, ent, goal);
+                ent
+            }
+            None => spawn_node(world, goal, place(idx)),
+        };
+        index.insert_node(idx, ent);
+    }
+
+    for idx in new.edge_indices() {
+        let (left, right) = new.edge_endpoints(idx).expect("edge index from this graph");
+        let link = Edge {
+            left: index.node_entity(left).expect("every goal was given an entity"),
+            right: index.node_entity(right).expect("every goal was given an entity"),
+        };
+        let ent = match stale_edges.remove(&new[idx].id) {
+            Some(ent) => {
+                world.write_storage::<Edge>().insert(ent, link).expect("reused edge entity is alive");
+                ent
+            }
+            None => world.create_entity().with(link).build(),
+        };
+        index.insert_edge(idx, ent);
+    }
+
+    let stale: Vec<Entity> = stale_edges.values().chain(stale_nodes.values()).copied().collect();
+    world.delete_entities(&stale).expect("stale entities are alive");
+    *world.write_resource::<GraphWorldIndex>() = index;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_util::{fixture, world};
+    use super::*;
+
+    fn at(idx: NodeIndex) -> Pos {
+        Pos { x: idx.index() as f64 * 100., y: 0. }
+    }
+
+    fn goal_entities(world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<Entity> {
+        let index = world.read_resource::<GraphWorldIndex>();
+        graph.node_indices().map(|idx| index.node_entity(idx).unwrap()).collect()
+    }
+
+    fn edge_entities(world: &World, graph: &DiGraph<ConsensusGoal, ConsensusEdge>) -> Vec<Entity> {
+        let index = world.read_resource::<GraphWorldIndex>();
+        graph.edge_indices().map(|idx| index.edge_entity(idx).unwrap()).collect()
+    }
+
+    #[test]
+    fn reconcile_keeps_goals_by_id() {
+        let mut world = world();
+        let old = fixture(3, &[]);
+        reconcile(&mut world, &DiGraph::new(), &old, at);
+        let before = goal_entities(&world, &old);
+        set_node_pos(&mut world, NodeIndex::new(0), Pos { x: 7., y: 7. });
+
+        // goal 1 goes and goal 3 takes its slot
+        let mut new = fixture(3, &[]);
+        new[NodeIndex::new(1)].id = 3;
+        new[NodeIndex::new(2)].st8mnt = "renamed".to_string();
+        reconcile(&mut world, &old, &new, at);
+        let after = goal_entities(&world, &new);
+
+        assert_eq!((after[0], after[2]), (before[0], before[2]));
+        assert!(!world.is_alive(before[1]));
+        assert!(world.is_alive(after[1]));
+        assert_eq!(node_pos(&world, NodeIndex::new(0)), Some(Pos { x: 7., y: 7. }));
+        assert_eq!(node_pos(&world, NodeIndex::new(1)), Some(at(NodeIndex::new(1))));
+        assert_eq!(world.read_storage::<Text>().get(after[2]).unwrap().st, "renamed");
+    }
+
+    #[test]
+    fn reconcile_keeps_edges_by_id_and_relinks_them() {
+        let mut world = world();
+        let old = fixture(3, &[(0, 1), (1, 2)]);
+        reconcile(&mut world, &DiGraph::new(), &old, at);
+        let before = edge_entities(&world, &old);
+
+        // edge 0 now runs 0 -> 2, edge 1 goes, edge 2 arrives
+        let mut new = fixture(3, &[(0, 2), (2, 1)]);
+        new[EdgeIndex::new(1)].id = 2;
+        reconcile(&mut world, &old, &new, at);
+        let (goals, after) = (goal_entities(&world, &new), edge_entities(&world, &new));
+
+        assert_eq!(after[0], before[0]);
+        assert!(!world.is_alive(before[1]));
+        let edges = world.read_storage::<Edge>();
+        let ends = |ent: Entity| edges.get(ent).map(|e| (e.left, e.right)).unwrap();
+        assert_eq!(ends(after[0]), (goals[0], goals[2]));
+        assert_eq!(ends(after[1]), (goals[2], goals[1]));
+    }
+}
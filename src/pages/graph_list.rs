@@ -0,0 +1,103 @@
+use seed::{prelude::*, *};
+
+// ------ ------
+//     Model
+// ------ ------
+
+/// What the backend tells us about a graph before we open it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GraphSummary {
+    pub id: String,
+    pub name: String,
+    pub owner: String,
+    pub nodes: usize,
+    pub modified: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Model {
+    graphs: Vec<GraphSummary>,
+    /// The graph currently open, if any.
+    open: Option<String>,
+    /// Why the last fetch failed, until one succeeds.
+    error: Option<String>,
+}
+
+// ------ ------
+//     Update
+// ------ ------
+
+#[derive(Debug)]
+pub enum Message {
+    Fetch,
+    Fetched(fetch::Result<Vec<GraphSummary>>),
+    Open(String),
+}
+
+pub fn update(
+    msg: Message,
+    model: &mut Model,
+    orders: &mut impl Orders<Message>,
+) -> Option<crate::Message> {
+    use Message::*;
+    match msg {
+        Fetch => {
+            orders.perform_cmd(async { Fetched(fetch_graphs().await) });
+        }
+        Fetched(Ok(graphs)) => {
+            model.graphs = graphs;
+            model.error = None;
+        }
+        Fetched(Err(err)) => {
+            log!("graphs not fetched", err);
+            model.error = Some(format!("{:?}", err));
+        }
+        Open(id) => {
+            model.open = Some(id.clone());
+            return Some(crate::Message::OpenGraph(id));
+        }
+    }
+    None
+}
+
+async fn fetch_graphs() -> fetch::Result<Vec<GraphSummary>> {
+    let result = Request::new("api/graph")
+        .method(Method::Get)
+        .fetch()
+        .await?
+        .check_status()?
+        .json()
+        .await?;
+    Ok(result)
+}
+
+// ------ ------
+//     View
+// ------ ------
+
+pub fn view(model: &Model) -> impl IntoNodes<Message> {
+    nodes![div![
+        button!["refresh graphs", ev(Ev::Click, |_| Message::Fetch)],
+        model.error.as_ref().map(|err| p![format!("could not fetch the graphs: {}", err)]),
+        table![
+            tr![th!["name"], th!["owner"], th!["goals"], th!["last modified"]],
+            model.graphs.iter().map(|graph| {
+                let id = graph.id.clone();
+                tr![
+                    IF!(model.open.as_ref() == Some(&graph.id) => style!{St::FontWeight => "bold"}),
+                    td![a![
+                        attrs! {At::Href => "#"},
+                        graph.name.as_str(),
+                        ev(Ev::Click, move |event| {
+                            event.prevent_default();
+                            Message::Open(id.clone())
+                        }),
+                    ]],
+                    td![graph.owner.as_str()],
+                    td![graph.nodes.to_string()],
+                    td![graph.modified.as_str()],
+                ]
+            }),
+        ],
+    ]]
+}
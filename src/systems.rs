@@ -1,6 +1,8 @@
 
 use crate::pages::cg_graph::WIDTH;
 use crate::pages::cg_graph::HEIGHT;
+use crate::pages::cg_graph::HANDLE;
+use crate::pages::cg_graph::{align, snap_to_grid, EdgePath, EdgeRouting, Guide, Guides, Rect, RouteCache, SnapGrid};
 use specs::WorldExt;
 use nalgebra::Vector2 as Vec2;
 use seed::log;
@@ -36,62 +38,234 @@ impl<'a> System<'a> for LineDraw {
     }
 }
 
+/// The goals and edges themselves.
+#[derive(SystemData)]
+pub struct GoalData<'a> {
+    dims: ReadStorage<'a, Dimension>,
+    poss: ReadStorage<'a, Pos>,
+    origins: ReadStorage<'a, Origin>,
+    texts: ReadStorage<'a, Text>,
+    edges: ReadStorage<'a, Edge>,
+    states: ReadStorage<'a, Interactable>,
+}
+
+/// What the analyses and the user have marked goals and edges with.
+#[derive(SystemData)]
+pub struct MarkData<'a> {
+    consensus: ReadStorage<'a, Consensus>,
+    plugged: ReadStorage<'a, Plugged>,
+    open: ReadStorage<'a, OpenLeaves>,
+    in_cycle: ReadStorage<'a, InCycle>,
+    lineage: ReadStorage<'a, Lineage>,
+    pinned: ReadStorage<'a, Pinned>,
+}
+
+/// What collapsing goals changes about the drawing.
+#[derive(SystemData)]
+pub struct FoldData<'a> {
+    hidden: ReadStorage<'a, Hidden>,
+    foldable: ReadStorage<'a, Foldable>,
+    collapsed: ReadStorage<'a, Collapsed>,
+    rerouted: ReadStorage<'a, Rerouted>,
+}
+
+/// What the mouse is up to.
+#[derive(SystemData)]
+pub struct PointerData<'a> {
+    selection: Read<'a, Selection>,
+    connecting: Read<'a, Connecting>,
+    mpos: Read<'a, MousePos>,
+}
+
+/// Where the canvas looks and how edges and guides go across it.
+#[derive(SystemData)]
+pub struct ViewData<'a> {
+    camera: Read<'a, Camera>,
+    routing: Read<'a, EdgeRouting>,
+    routes: Write<'a, RouteCache<Entity>>,
+    guides: Read<'a, Guides>,
+}
+
 impl<'a> System<'a> for Renderer {
 
-    type SystemData = (
-        ReadStorage<'a, Dimension>,
-        ReadStorage<'a, Pos>,
-        ReadStorage<'a, Origin>,
-        ReadStorage<'a, Text>,
-        ReadStorage<'a, Edge>,
-        Entities<'a>,
-        ReadStorage<'a, Interactable>
-    );
-    fn run(&mut self, (dims, poss, origins, texts, edges, ents, states): Self::SystemData) {
+    type SystemData = (Entities<'a>, GoalData<'a>, MarkData<'a>, FoldData<'a>, PointerData<'a>, ViewData<'a>);
+    fn run(&mut self, (ents, goals, marks, folds, pointer, view): Self::SystemData) {
+        let GoalData { dims, poss, origins, texts, edges, states } = goals;
+        let MarkData { consensus, plugged, open, in_cycle, lineage, pinned } = marks;
+        let FoldData { hidden, foldable, collapsed, rerouted } = folds;
+        let PointerData { selection, connecting, mpos } = pointer;
+        let ViewData { camera, routing, mut routes, guides } = view;
         let canvas = self.canv_ref.get().expect("get canvas element");
         let ctx = seed::canvas_context_2d(&canvas);
         ctx.set_fill_style(&JsValue::from("#000000"));
         ctx.clear_rect(0., 0., WIDTH as f64, HEIGHT as f64);
-        let mut black = false;
-        for (dim, pos, _orig, state) in (&dims, &poss, &origins,  &states).join() {
-            match (black, state) {
-                (_, Interactable::Hover) => {
-
-                    ctx.set_fill_style(&JsValue::from("#FF0000"));
-                    ctx.fill_rect(pos.x, pos.y, dim.w, dim.h);
+        ctx.save();
+        ctx.translate(-camera.x, -camera.y).unwrap();
+        for (ent, dim, pos, _orig, state, _) in (&ents, &dims, &poss, &origins,  &states, !&hidden).join() {
+            let rank = lineage.get(ent);
+            ctx.set_global_alpha(if rank == Some(&Lineage::Dimmed) { 0.25 } else { 1. });
+            ctx.set_fill_style(&JsValue::from(node_fill(state, consensus.get(ent))));
+            ctx.fill_rect(pos.x, pos.y, dim.w, dim.h);
+            ctx.set_fill_style(&JsValue::from("#000000"));
+            // open goals get an orange frame, and say how much is still open under them
+            if let Some(Plugged(false)) = plugged.get(ent) {
+                ctx.set_stroke_style(&JsValue::from("#FF8800"));
+                ctx.stroke_rect(pos.x, pos.y, dim.w, dim.h);
+                ctx.set_stroke_style(&JsValue::from("#000000"));
+            }
+            if in_cycle.contains(ent) {
+                ctx.set_stroke_style(&JsValue::from("#FF0000"));
+                ctx.stroke_rect(pos.x - 6., pos.y - 6., dim.w + 12., dim.h + 12.);
+                ctx.set_stroke_style(&JsValue::from("#000000"));
+            }
+            if let Some(OpenLeaves(n)) = open.get(ent).filter(|n| n.0 > 0) {
+                ctx.fill_text(&format!("{} open", n), pos.x, pos.y - 4.).unwrap();
+            }
+            ctx.set_fill_style(&JsValue::from("#FFFFFF"));
+            ctx.fill_rect(pos.x + dim.w - HANDLE, pos.y + dim.h - HANDLE, HANDLE, HANDLE);
+            match collapsed.get(ent) {
+                Some(Collapsed(n)) => {
+                    ctx.fill_rect(pos.x + dim.w - HANDLE, pos.y, HANDLE, HANDLE);
+                    ctx.fill_text(&format!("+{}", n), pos.x + 2., pos.y + dim.h / 2.).unwrap();
                     ctx.set_fill_style(&JsValue::from("#000000"));
-
+                    ctx.fill_text("+", pos.x + dim.w - HANDLE + 2., pos.y + HANDLE - 1.).unwrap();
                 }
-                (_, Interactable::MouseDown(_, _)) => {
-
-                    ctx.set_fill_style(&JsValue::from("#00FF00"));
-                    ctx.fill_rect(pos.x, pos.y, dim.w, dim.h);
+                // leaves have nothing to collapse, so no toggle
+                None if foldable.contains(ent) => {
+                    ctx.fill_rect(pos.x + dim.w - HANDLE, pos.y, HANDLE, HANDLE);
                     ctx.set_fill_style(&JsValue::from("#000000"));
-
+                    ctx.fill_text("-", pos.x + dim.w - HANDLE + 3., pos.y + HANDLE - 1.).unwrap();
                 }
-                (_, _) => {
-
-                    ctx.set_fill_style(&JsValue::from("#000000"));
-                    ctx.fill_rect(pos.x, pos.y, dim.w, dim.h);
-
+                None => ctx.set_fill_style(&JsValue::from("#000000")),
+            }
+            if pinned.contains(ent) {
+                ctx.fill_rect(pos.x + 2., pos.y + 2., 4., 4.);
+            }
+            if let Some(txt) = texts.get(ent) {
+                ctx.fill_text_with_max_width(&txt.st, pos.x, pos.y + dim.h + 12., dim.w * 2.).unwrap();
+            }
+            if selection.0 == Some(ent) {
+                ctx.stroke_rect(pos.x - 3., pos.y - 3., dim.w + 6., dim.h + 6.);
+            }
+            if let Some(colour) = lineage_colour(rank) {
+                ctx.set_stroke_style(&JsValue::from(colour));
+                ctx.stroke_rect(pos.x - 3., pos.y - 3., dim.w + 6., dim.h + 6.);
+                ctx.set_stroke_style(&JsValue::from("#000000"));
+            }
+        }
+        ctx.set_global_alpha(1.);
+        if let Some((pos, dim)) = connecting.0.and_then(|ent| Some((poss.get(ent)?, dims.get(ent)?))) {
+            ctx.begin_path();
+            ctx.move_to(pos.x + dim.w, pos.y + dim.h);
+            ctx.line_to(mpos.x, mpos.y);
+            ctx.stroke();
+        }
+        let goals: Vec<(Entity, Rect)> = (&ents, &poss, &dims, !&hidden)
+            .join()
+            .map(|(ent, pos, dim, _)| (ent, Rect::of(pos, dim)))
+            .collect();
+        let links: Vec<(Entity, (Entity, Entity))> = (&ents, &edges)
+            .join()
+            .map(|(ent, edge)| (ent, rerouted.get(ent).map_or((edge.left, edge.right), |r| (r.left, r.right))))
+            .collect();
+        let ends: Vec<(Entity, Entity)> = links.iter().map(|(_, ends)| *ends).collect();
+        for ((ent, _), routed) in links.iter().zip(routes.routes(goals, ends, *routing)) {
+            let routed = match routed {
+                Some(routed) => routed,
+                None => continue,
+            };
+            let rank = lineage.get(*ent);
+            ctx.set_global_alpha(if rank == Some(&Lineage::Dimmed) { 0.25 } else { 1. });
+            if let Some(colour) = lineage_colour(rank) {
+                ctx.set_stroke_style(&JsValue::from(colour));
+                ctx.set_fill_style(&JsValue::from(colour));
+                ctx.set_line_width(3.);
+            }
+            if selection.0 == Some(*ent) {
+                ctx.set_line_width(4.);
+            }
+            ctx.begin_path();
+            match &routed.path {
+                EdgePath::Polyline(points) => {
+                    ctx.move_to(points[0].0, points[0].1);
+                    for (x, y) in &points[1..] {
+                        ctx.line_to(*x, *y);
+                    }
+                }
+                EdgePath::Curve { from, ctrl, to } => {
+                    ctx.move_to(from.0, from.1);
+                    ctx.quadratic_curve_to(ctrl.0, ctrl.1, to.0, to.1);
                 }
             }
-            // ctx.fill_text_with_max_width(&txt.st, pos.x, pos.y, dim.w).unwrap();
-            // ctx.fill_rect(pos.x, pos.y, dim.w, dim.h);
+            ctx.stroke();
+            if let Some([tip, a, b]) = routed.arrow {
+                ctx.begin_path();
+                ctx.move_to(tip.0, tip.1);
+                ctx.line_to(a.0, a.1);
+                ctx.line_to(b.0, b.1);
+                ctx.close_path();
+                ctx.fill();
+            }
+            ctx.set_stroke_style(&JsValue::from("#000000"));
+            ctx.set_fill_style(&JsValue::from("#000000"));
+            ctx.set_line_width(1.);
         }
-        for edge in (&edges).join() {
-            let left = poss.get(edge.left).unwrap();
-            let right = poss.get(edge.right).unwrap();
+        ctx.set_global_alpha(1.);
+        // whatever the goal being dragged lines up with, across the whole view
+        ctx.set_stroke_style(&JsValue::from("#00AAFF"));
+        for guide in &guides.0 {
             ctx.begin_path();
-            ctx.move_to(left.x, left.y);
-            ctx.line_to(right.x, right.y);
+            match guide {
+                Guide::Vertical(x) => {
+                    ctx.move_to(*x, camera.y);
+                    ctx.line_to(*x, camera.y + HEIGHT as f64);
+                }
+                Guide::Horizontal(y) => {
+                    ctx.move_to(camera.x, *y);
+                    ctx.line_to(camera.x + WIDTH as f64, *y);
+                }
+            }
             ctx.stroke();
         }
+        ctx.set_stroke_style(&JsValue::from("#000000"));
+        ctx.restore();
     }
 }
 
 pub struct Hover;
 
+pub(crate) fn lineage_colour(rank: Option<&Lineage>) -> Option<&'static str> {
+    match rank? {
+        Lineage::Focus | Lineage::Dimmed => None,
+        Lineage::Ancestor => Some("#00AA00"),
+        Lineage::Descendant => Some("#AA00AA"),
+    }
+}
+
+pub(crate) fn node_fill(state: &Interactable, consensus: Option<&Consensus>) -> String {
+    match state {
+        Interactable::Hover => "#FF0000".to_string(),
+        Interactable::MouseDown(_, _) => "#00FF00".to_string(),
+        // heavier goals go from black towards blue
+        _ => consensus.map_or("#000000".to_string(), |c| format!("#0000{:0>2x}", (c.share * 255.) as u8)),
+    }
+}
+
+/// Where the top left corner of the canvas sits in the graph's coordinates.
+#[derive(Debug, Default)]
+pub struct Camera {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// The goal or edge entity the user last clicked on.
+#[derive(Debug, Default)]
+pub struct Selection(pub Option<Entity>);
+
+/// The goal entity an edge is being dragged out of.
+#[derive(Debug, Default)]
+pub struct Connecting(pub Option<Entity>);
+
 #[derive(Debug, Default)]
 pub struct MousePos {
     pub x: f64,
@@ -118,19 +292,41 @@ pub struct Drag;
 impl<'a> System<'a> for Drag {
 
     type SystemData = (
+        Entities<'a>,
         WriteStorage<'a, Pos>,
+        ReadStorage<'a, Dimension>,
+        ReadStorage<'a, Hidden>,
         ReadStorage<'a, Interactable>,
-        Read<'a, MousePos>
+        Read<'a, MousePos>,
+        Read<'a, SnapGrid>,
+        Write<'a, Guides>,
     );
-    fn run(&mut self, (mut poss, states, mpos): Self::SystemData) {
-
-        for (mut pos, st8) in (&mut poss, &states).join() {
-            match st8  {
-                Interactable::MouseDown(x, y) => {
-                    pos.x = mpos.x - x;
-                    pos.y = mpos.y - y;
-                }
-                _ => {}
+    fn run(&mut self, (ents, mut poss, dims, hidden, states, mpos, grid, mut guides): Self::SystemData) {
+        let held: Vec<(Entity, f64, f64)> = (&ents, &states)
+            .join()
+            .filter_map(|(ent, st8)| match st8 {
+                Interactable::MouseDown(x, y) => Some((ent, *x, *y)),
+                _ => None,
+            })
+            .collect();
+        let others: Vec<Rect> = (&ents, &poss, &dims, !&hidden)
+            .join()
+            .filter(|(ent, ..)| !held.iter().any(|(h, ..)| h == ent))
+            .map(|(_, pos, dim, _)| Rect::of(pos, dim))
+            .collect();
+        guides.0.clear();
+        for (ent, x, y) in held {
+            let mut to = Pos { x: mpos.x - x, y: mpos.y - y };
+            // with the grid on, goals line up on the grid instead of with their neighbours
+            if grid.on {
+                to = snap_to_grid(to, grid.size);
+            } else if let Some(dim) = dims.get(ent) {
+                let (aligned, lines) = align(Rect::of(&to, dim), &others);
+                to = aligned;
+                guides.0.extend(lines);
+            }
+            if let Some(pos) = poss.get_mut(ent) {
+                *pos = to;
             }
         }
     }
@@ -144,10 +340,11 @@ impl<'a> System<'a> for Interactable {
         ReadStorage<'a, Pos>,
         ReadStorage<'a, Origin>,
         WriteStorage<'a, Interactable>,
-        Read<'a, MousePos>
+        Read<'a, MousePos>,
+        ReadStorage<'a, Hidden>,
     );
-    fn run(&mut self, (dims, poss, origins, mut inter, mpos): Self::SystemData) {
-        for (dims, pos, inter) in (&dims, &poss, &mut inter).join() {
+    fn run(&mut self, (dims, poss, origins, mut inter, mpos, hidden): Self::SystemData) {
+        for (dims, pos, inter, _) in (&dims, &poss, &mut inter, !&hidden).join() {
             let hovering = mpos.x > pos.x && mpos.x < pos.x + dims.w && mpos.y > pos.y && mpos.y < pos.y + dims.h;
             match (hovering, &*inter) {
                 (true, Interactable::Nothing) => *inter = Interactable::Hover,